@@ -0,0 +1,346 @@
+use bevy::prelude::*;
+use ranger_physics::{AABB, Path};
+
+const EPSILON: f32 = 0.00001;
+
+/// Makes an actor steer around other actors instead of into them.
+///
+/// Everybody doing this takes half of the responsibility to avoid a collision, which is what keeps
+/// crowds from oscillating. Actors without this component are still avoided, they just don't help.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Avoidance {
+    /// Actors further away than this are ignored
+    pub neighbour_distance: f32,
+    /// How many seconds ahead collisions are looked for. Higher is safer but more timid.
+    pub time_horizon: f32,
+    pub max_neighbours: usize,
+    /// The velocity picked last update, which is what the neighbours get to see
+    pub velocity: Vec2,
+}
+
+impl Default for Avoidance {
+    fn default() -> Self {
+        Self {
+            neighbour_distance: 150.0,
+            time_horizon: 1.0,
+            max_neighbours: 8,
+            velocity: Vec2::ZERO,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Agent {
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub radius: f32,
+}
+
+impl Agent {
+    /// The radius of the circle an agent with this bounding box is treated as, both for
+    /// avoidance and for building navmeshes
+    pub fn radius(aabb: &AABB) -> f32 {
+        aabb.width.max(aabb.height) / 2.0
+    }
+}
+
+/// Every velocity on the left side of the line is allowed
+#[derive(Debug, Clone, Copy)]
+struct Line {
+    point: Vec2,
+    direction: Vec2,
+}
+
+/// Optimal reciprocal collision avoidance, following RVO2
+/// https://gamma.cs.unc.edu/ORCA/
+///
+/// Each neighbour turns into a half-plane of allowed velocities, then the velocity closest to
+/// `preferred` inside all of them is picked. If there isn't one, it picks whichever velocity
+/// violates them the least.
+pub fn orca_velocity(
+    agent: &Agent,
+    preferred: Vec2,
+    max_speed: f32,
+    neighbours: &[Agent],
+    time_horizon: f32,
+    delta_seconds: f32,
+) -> Vec2 {
+    let inverse_time_horizon = 1.0 / time_horizon;
+    let mut lines = vec![];
+
+    for other in neighbours.iter() {
+        let relative_position = other.position - agent.position;
+        let relative_velocity = agent.velocity - other.velocity;
+        let distance_squared = relative_position.length_squared();
+        let combined_radius = agent.radius + other.radius;
+        let combined_radius_squared = combined_radius * combined_radius;
+
+        let direction;
+        let u;
+
+        if distance_squared > combined_radius_squared {
+            // vector from the cutoff centre to the relative velocity
+            let w = relative_velocity - inverse_time_horizon * relative_position;
+            let w_length_squared = w.length_squared();
+            let dot = w.dot(relative_position);
+
+            if dot < 0.0 && dot * dot > combined_radius_squared * w_length_squared {
+                // closest to the cutoff circle
+                let w_length = w_length_squared.sqrt();
+                let unit_w = w / w_length;
+
+                direction = Vec2::new(unit_w.y, -unit_w.x);
+                u = (combined_radius * inverse_time_horizon - w_length) * unit_w;
+            } else {
+                // closest to one of the legs of the cone
+                let leg = (distance_squared - combined_radius_squared).sqrt();
+
+                direction = if relative_position.perp_dot(w) > 0.0 {
+                    Vec2::new(
+                        relative_position.x * leg - relative_position.y * combined_radius,
+                        relative_position.x * combined_radius + relative_position.y * leg,
+                    ) / distance_squared
+                } else {
+                    -Vec2::new(
+                        relative_position.x * leg + relative_position.y * combined_radius,
+                        -relative_position.x * combined_radius + relative_position.y * leg,
+                    ) / distance_squared
+                };
+
+                u = relative_velocity.dot(direction) * direction - relative_velocity;
+            }
+        } else {
+            // already overlapping, get out within this update
+            let inverse_delta = 1.0 / delta_seconds;
+            let w = relative_velocity - inverse_delta * relative_position;
+            let w_length = w.length();
+            // right on top of each other, there's no telling which way is out
+            if w_length <= EPSILON {
+                continue;
+            }
+            let unit_w = w / w_length;
+
+            direction = Vec2::new(unit_w.y, -unit_w.x);
+            u = (combined_radius * inverse_delta - w_length) * unit_w;
+        }
+
+        lines.push(Line { point: agent.velocity + 0.5 * u, direction });
+    }
+
+    let mut result = Vec2::ZERO;
+    let failed_line = linear_program_2(&lines, max_speed, preferred, false, &mut result);
+    if failed_line < lines.len() {
+        linear_program_3(&lines, failed_line, max_speed, &mut result);
+    }
+
+    result
+}
+
+/// Finds the best velocity on line `line_index`, within the speed limit and all lines before it
+fn linear_program_1(
+    lines: &[Line],
+    line_index: usize,
+    radius: f32,
+    optimal: Vec2,
+    optimize_direction: bool,
+    result: &mut Vec2,
+) -> bool {
+    let line = lines[line_index];
+    let dot = line.point.dot(line.direction);
+    let discriminant = dot * dot + radius * radius - line.point.length_squared();
+
+    // the line misses the speed limit circle entirely
+    if discriminant < 0.0 {
+        return false;
+    }
+
+    let discriminant = discriminant.sqrt();
+    let mut t_left = -dot - discriminant;
+    let mut t_right = -dot + discriminant;
+
+    for other in lines[..line_index].iter() {
+        let denominator = line.direction.perp_dot(other.direction);
+        let numerator = other.direction.perp_dot(line.point - other.point);
+
+        // parallel lines
+        if denominator.abs() <= EPSILON {
+            if numerator < 0.0 {
+                return false;
+            }
+            continue;
+        }
+
+        let t = numerator / denominator;
+        if denominator >= 0.0 {
+            t_right = t_right.min(t);
+        } else {
+            t_left = t_left.max(t);
+        }
+
+        if t_left > t_right {
+            return false;
+        }
+    }
+
+    *result = if optimize_direction {
+        match optimal.dot(line.direction) > 0.0 {
+            true => line.point + t_right * line.direction,
+            false => line.point + t_left * line.direction,
+        }
+    } else {
+        let t = line.direction.dot(optimal - line.point).clamp(t_left, t_right);
+        line.point + t * line.direction
+    };
+
+    true
+}
+
+/// Returns the index of the line it failed on, or the amount of lines if it didn't fail
+fn linear_program_2(
+    lines: &[Line],
+    radius: f32,
+    optimal: Vec2,
+    optimize_direction: bool,
+    result: &mut Vec2,
+) -> usize {
+    *result = if optimize_direction {
+        optimal * radius
+    } else if optimal.length_squared() > radius * radius {
+        optimal.normalize() * radius
+    } else {
+        optimal
+    };
+
+    for (i, line) in lines.iter().enumerate() {
+        if line.direction.perp_dot(line.point - *result) <= 0.0 {
+            continue;
+        }
+
+        let previous = *result;
+        if !linear_program_1(lines, i, radius, optimal, optimize_direction, result) {
+            *result = previous;
+            return i;
+        }
+    }
+
+    lines.len()
+}
+
+/// Nothing satisfies all lines, so this minimizes how far the worst one gets violated
+fn linear_program_3(lines: &[Line], begin: usize, radius: f32, result: &mut Vec2) {
+    let mut distance = 0.0;
+
+    for (i, line) in lines.iter().enumerate().skip(begin) {
+        if line.direction.perp_dot(line.point - *result) <= distance {
+            continue;
+        }
+
+        let mut projected_lines = vec![];
+        for other in lines[..i].iter() {
+            let determinant = line.direction.perp_dot(other.direction);
+
+            let point = if determinant.abs() <= EPSILON {
+                // pointing the same way, so the other one is already covered
+                if line.direction.dot(other.direction) > 0.0 {
+                    continue;
+                }
+                0.5 * (line.point + other.point)
+            } else {
+                line.point + (other.direction.perp_dot(line.point - other.point) / determinant) * line.direction
+            };
+
+            projected_lines.push(Line {
+                point,
+                direction: (other.direction - line.direction).normalize_or_zero(),
+            });
+        }
+
+        let previous = *result;
+        let optimal = Vec2::new(-line.direction.y, line.direction.x);
+        if linear_program_2(&projected_lines, radius, optimal, true, result) < projected_lines.len() {
+            // shouldn't happen in principle, floating point says otherwise
+            *result = previous;
+        }
+
+        distance = line.direction.perp_dot(line.point - *result);
+    }
+}
+
+/// Runs after the steering has set `Path::movement` to where everybody wants to go,
+/// and bends it so they don't walk into each other on the way.
+#[allow(clippy::type_complexity)]
+pub fn avoid_neighbours(
+    mut actor_query: Query<(Entity, &AABB, &mut Path, Option<&mut Avoidance>, Has<crate::Stunned>)>,
+    res_time: Res<Time>,
+) {
+    let delta_seconds = res_time.delta_seconds();
+    if delta_seconds <= 0.0 {
+        return;
+    }
+
+    let actors: Vec<(Entity, Agent)> = actor_query.iter()
+        .map(|(entity, aabb, path, avoidance, stunned)| (entity, Agent {
+            position: aabb.point.truncate(),
+            velocity: match stunned {
+                true => Vec2::ZERO,
+                false => avoidance.map_or(path.movement.truncate(), |avoidance| avoidance.velocity),
+            },
+            radius: Agent::radius(aabb),
+        }))
+        .collect();
+
+    for (entity, aabb, mut path, avoidance, stunned) in actor_query.iter_mut() {
+        // stunned agents stay put, everyone else goes around them
+        let Some(mut avoidance) = avoidance.filter(|_| !stunned) else {
+            continue;
+        };
+
+        let agent = Agent {
+            position: aabb.point.truncate(),
+            velocity: avoidance.velocity,
+            radius: Agent::radius(aabb),
+        };
+
+        let mut neighbours: Vec<Agent> = actors.iter()
+            .filter(|(other, _)| *other != entity)
+            .map(|(_, other)| *other)
+            .filter(|other| other.position.distance(agent.position) <= avoidance.neighbour_distance)
+            .collect();
+        neighbours.sort_by(|a, b| {
+            a.position.distance_squared(agent.position).total_cmp(&b.position.distance_squared(agent.position))
+        });
+        neighbours.truncate(avoidance.max_neighbours);
+
+        let preferred = path.movement.truncate();
+        let max_speed = path.velocity.max(preferred.length());
+        let velocity = orca_velocity(&agent, preferred, max_speed, &neighbours, avoidance.time_horizon, delta_seconds);
+
+        avoidance.velocity = velocity;
+        path.movement = velocity.extend(path.movement.z);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn head_on_agents_sidestep() {
+        let agent = Agent { position: Vec2::ZERO, velocity: Vec2::new(100.0, 0.0), radius: 25.0 };
+        let other = Agent { position: Vec2::new(100.0, 0.0), velocity: Vec2::new(-100.0, 0.0), radius: 25.0 };
+
+        let velocity = orca_velocity(&agent, Vec2::new(100.0, 0.0), 100.0, &[other], 1.0, 1.0 / 60.0);
+
+        assert!(velocity.y.abs() > 1.0);
+        assert!(velocity.length() <= 100.0 + EPSILON);
+    }
+
+    #[test]
+    fn nobody_around_means_no_change() {
+        let agent = Agent { position: Vec2::ZERO, velocity: Vec2::ZERO, radius: 25.0 };
+
+        let velocity = orca_velocity(&agent, Vec2::new(30.0, 40.0), 100.0, &[], 1.0, 1.0 / 60.0);
+
+        assert_eq!(velocity, Vec2::new(30.0, 40.0));
+    }
+}
@@ -0,0 +1,446 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use serde::Deserialize;
+
+use crate::{blackboard::keys, Blackboard, Target};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Success,
+    Failure,
+    Running,
+}
+
+/// Built-in leaf checks against the blackboard, so trees can branch without the game registering
+/// an action for every little comparison
+#[derive(Debug, Clone, Deserialize)]
+pub enum Check {
+    Exists(String),
+    IsTrue(String),
+    Below(String, f32),
+    Above(String, f32),
+    HasTarget,
+}
+
+impl Check {
+    fn evaluate(&self, blackboard: &Blackboard, target: Option<&Target>) -> bool {
+        match self {
+            Check::Exists(key) => blackboard.contains(key),
+            Check::IsTrue(key) => blackboard.bool(key).unwrap_or(false),
+            Check::Below(key, value) => blackboard.float(key).is_some_and(|float| float < *value),
+            Check::Above(key, value) => blackboard.float(key).is_some_and(|float| float > *value),
+            Check::HasTarget => target.is_some_and(|target| target.has_target()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub enum Node {
+    /// Runs the children in order until one of them doesn't succeed
+    Sequence(Vec<Node>),
+    /// Runs the children in order until one of them doesn't fail. Starts over from the first
+    /// child every tick, so a higher priority child can take over from one that's still running.
+    Selector(Vec<Node>),
+    /// Runs all children every tick. Succeeds once `succeed_at` children have succeeded and fails
+    /// as soon as that's not possible anymore.
+    Parallel { succeed_at: usize, children: Vec<Node> },
+    /// Fails without running the child until `seconds` have passed since it last finished
+    Cooldown { seconds: f32, child: Box<Node> },
+    /// Runs the child until it succeeded `times` times, or forever if there's no count.
+    /// Fails when the child does.
+    Repeat { times: Option<u32>, child: Box<Node> },
+    Invert(Box<Node>),
+    Condition(Check),
+    Wait(f32),
+    /// Looks up an action registered in `BehaviorActions`
+    Action(String),
+}
+
+impl Node {
+    fn children(&self) -> &[Node] {
+        match self {
+            Node::Sequence(children) | Node::Selector(children) => children,
+            Node::Parallel { children, .. } => children,
+            Node::Cooldown { child, .. } | Node::Repeat { child, .. } => std::slice::from_ref(child),
+            Node::Invert(child) => std::slice::from_ref(child),
+            Node::Condition(_) | Node::Wait(_) | Node::Action(_) => &[],
+        }
+    }
+
+    fn count(&self) -> usize {
+        1 + self.children().iter().map(Node::count).sum::<usize>()
+    }
+}
+
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct BehaviorTreeAsset {
+    pub root: Node,
+}
+
+/// What an action gets to work with. Actions talk to the rest of the game through the blackboard.
+pub struct ActionContext<'a> {
+    pub entity: Entity,
+    pub position: Vec3,
+    pub target: Option<&'a Target>,
+    pub blackboard: &'a mut Blackboard,
+    pub now: f32,
+}
+
+pub type Action = fn(&mut ActionContext) -> Status;
+
+/// The actions trees can refer to by name
+#[derive(Resource)]
+pub struct BehaviorActions(HashMap<String, Action>);
+
+impl BehaviorActions {
+    pub fn register(&mut self, name: &str, action: Action) {
+        self.0.insert(name.to_string(), action);
+    }
+}
+
+impl Default for BehaviorActions {
+    fn default() -> Self {
+        let mut actions = Self(HashMap::new());
+        actions.register("move_to_target", move_to_target);
+        actions.register("move_to_last_known", move_to_last_known);
+        actions.register("flee_from_target", flee_from_target);
+        actions.register("stop", stop);
+        actions
+    }
+}
+
+// how close counts as arrived for the movement actions
+const ARRIVAL_DISTANCE: f32 = 10.0;
+
+fn move_to_target(context: &mut ActionContext) -> Status {
+    let Some(point) = context.target.and_then(|target| target.point) else {
+        return Status::Failure;
+    };
+
+    context.blackboard.set_vector(keys::MOVE_TO, point);
+    Status::Success
+}
+
+fn move_to_last_known(context: &mut ActionContext) -> Status {
+    let Some(last_known) = context.target.and_then(|target| target.last_known) else {
+        return Status::Failure;
+    };
+
+    if context.position.distance(last_known) <= ARRIVAL_DISTANCE {
+        context.blackboard.remove(keys::MOVE_TO);
+        return Status::Success;
+    }
+
+    context.blackboard.set_vector(keys::MOVE_TO, last_known);
+    Status::Running
+}
+
+fn flee_from_target(context: &mut ActionContext) -> Status {
+    let Some(point) = context.target.and_then(|target| target.point) else {
+        return Status::Failure;
+    };
+
+    let away = (context.position - point).normalize_or_zero();
+    context.blackboard.set_vector(keys::MOVE_TO, context.position + away * 100.0);
+    Status::Success
+}
+
+fn stop(context: &mut ActionContext) -> Status {
+    context.blackboard.remove(keys::MOVE_TO);
+    Status::Success
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct NodeState {
+    // the child a sequence is currently at, or the one a selector has running
+    child: usize,
+    // when a cooldown is ready again, or when a wait started
+    time: Option<f32>,
+    count: u32,
+}
+
+/// Runs a behavior tree asset for one entity. The tree is shared, the state of its nodes isn't.
+#[derive(Component)]
+pub struct BehaviorTree {
+    pub handle: Handle<BehaviorTreeAsset>,
+    pub status: Option<Status>,
+    states: Vec<NodeState>,
+}
+
+impl BehaviorTree {
+    pub fn new(handle: Handle<BehaviorTreeAsset>) -> Self {
+        Self { handle, status: None, states: vec![] }
+    }
+
+    pub fn reset(&mut self) {
+        self.states.clear();
+        self.status = None;
+    }
+
+    fn tick(&mut self, tree: &BehaviorTreeAsset, actions: &BehaviorActions, context: &mut ActionContext) -> Status {
+        // either the first tick, or the asset got hot reloaded into something else
+        if self.states.len() != tree.root.count() {
+            self.states = vec![NodeState::default(); tree.root.count()];
+        }
+
+        let status = tick_node(&tree.root, 0, &mut self.states, actions, context);
+        self.status = Some(status);
+        status
+    }
+}
+
+/// Nodes are identified by their pre-order index, which is how their state is looked up.
+fn tick_node(
+    node: &Node,
+    index: usize,
+    states: &mut [NodeState],
+    actions: &BehaviorActions,
+    context: &mut ActionContext,
+) -> Status {
+    match node {
+        Node::Sequence(children) => {
+            while states[index].child < children.len() {
+                let child = states[index].child;
+                let child_index = child_index(children, index, child);
+
+                match tick_node(&children[child], child_index, states, actions, context) {
+                    Status::Running => return Status::Running,
+                    Status::Success => states[index].child += 1,
+                    Status::Failure => {
+                        states[index].child = 0;
+                        return Status::Failure;
+                    },
+                }
+            }
+
+            states[index].child = 0;
+            Status::Success
+        },
+        Node::Selector(children) => {
+            let running = states[index].child;
+
+            for (child, child_node) in children.iter().enumerate() {
+                let status = tick_node(child_node, child_index(children, index, child), states, actions, context);
+                if status == Status::Failure {
+                    continue;
+                }
+
+                // whatever was running further down got interrupted, and starts over next time
+                if child < running {
+                    let start = child_index(children, index, running);
+                    states[start..start + children[running].count()].fill(NodeState::default());
+                }
+
+                states[index].child = match status {
+                    Status::Running => child,
+                    _ => 0,
+                };
+                return status;
+            }
+
+            states[index].child = 0;
+            Status::Failure
+        },
+        Node::Parallel { succeed_at, children } => {
+            let mut succeeded = 0;
+            let mut failed = 0;
+
+            for (child, child_node) in children.iter().enumerate() {
+                match tick_node(child_node, child_index(children, index, child), states, actions, context) {
+                    Status::Success => succeeded += 1,
+                    Status::Failure => failed += 1,
+                    Status::Running => {},
+                }
+            }
+
+            let status = if succeeded >= *succeed_at {
+                Status::Success
+            } else if children.len() - failed < *succeed_at {
+                Status::Failure
+            } else {
+                return Status::Running;
+            };
+
+            // children still running got cut short, and start over next time
+            states[index + 1..index + node.count()].fill(NodeState::default());
+            status
+        },
+        Node::Cooldown { seconds, child } => {
+            if states[index].time.is_some_and(|ready_at| context.now < ready_at) {
+                return Status::Failure;
+            }
+
+            let status = tick_node(child, index + 1, states, actions, context);
+            if status != Status::Running {
+                states[index].time = Some(context.now + seconds);
+            }
+
+            status
+        },
+        Node::Repeat { times, child } => {
+            match tick_node(child, index + 1, states, actions, context) {
+                Status::Success => {
+                    states[index].count += 1;
+
+                    if times.is_some_and(|times| states[index].count >= times) {
+                        states[index].count = 0;
+                        return Status::Success;
+                    }
+
+                    Status::Running
+                },
+                Status::Failure => {
+                    states[index].count = 0;
+                    Status::Failure
+                },
+                Status::Running => Status::Running,
+            }
+        },
+        Node::Invert(child) => {
+            match tick_node(child, index + 1, states, actions, context) {
+                Status::Success => Status::Failure,
+                Status::Failure => Status::Success,
+                Status::Running => Status::Running,
+            }
+        },
+        Node::Condition(check) => {
+            match check.evaluate(context.blackboard, context.target) {
+                true => Status::Success,
+                false => Status::Failure,
+            }
+        },
+        Node::Wait(seconds) => {
+            let started_at = *states[index].time.get_or_insert(context.now);
+
+            if context.now - started_at < *seconds {
+                return Status::Running;
+            }
+
+            states[index].time = None;
+            Status::Success
+        },
+        Node::Action(name) => {
+            let Some(action) = actions.0.get(name) else {
+                warn!("behavior tree refers to unknown action {}", name);
+                return Status::Failure;
+            };
+
+            action(context)
+        },
+    }
+}
+
+fn child_index(children: &[Node], parent: usize, child: usize) -> usize {
+    parent + 1 + children[..child].iter().map(Node::count).sum::<usize>()
+}
+
+#[allow(clippy::type_complexity)]
+pub fn tick_behavior_trees(
+    mut tree_query: Query<(Entity, &mut BehaviorTree, &mut Blackboard, &Transform, Option<&Target>), Without<crate::Stunned>>,
+    res_trees: Res<Assets<BehaviorTreeAsset>>,
+    res_actions: Res<BehaviorActions>,
+    res_time: Res<Time>,
+) {
+    for (entity, mut behavior_tree, mut blackboard, transform, target) in tree_query.iter_mut() {
+        // still loading
+        let Some(tree) = res_trees.get(&behavior_tree.handle) else {
+            continue;
+        };
+
+        let mut context = ActionContext {
+            entity,
+            position: transform.translation,
+            target,
+            blackboard: &mut blackboard,
+            now: res_time.elapsed_seconds(),
+        };
+
+        behavior_tree.tick(tree, &res_actions, &mut context);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(tree: &mut BehaviorTree, asset: &BehaviorTreeAsset, blackboard: &mut Blackboard, now: f32) -> Status {
+        let mut context = ActionContext {
+            entity: Entity::PLACEHOLDER,
+            position: Vec3::ZERO,
+            target: None,
+            blackboard,
+            now,
+        };
+
+        tree.tick(asset, &BehaviorActions::default(), &mut context)
+    }
+
+    #[test]
+    fn trees_load_from_ron_and_keep_node_state() {
+        let asset: BehaviorTreeAsset = ron::from_str(r#"(
+            root: Selector([
+                Sequence([Condition(Below("health", 10.0)), Action("stop")]),
+                Cooldown(seconds: 1.0, child: Wait(0.5)),
+            ]),
+        )"#).unwrap();
+        let mut tree = BehaviorTree::new(Handle::default());
+        let mut blackboard = Blackboard::new();
+        blackboard.set_float("health", 50.0);
+
+        assert_eq!(tick(&mut tree, &asset, &mut blackboard, 0.0), Status::Running);
+        assert_eq!(tick(&mut tree, &asset, &mut blackboard, 0.6), Status::Success);
+        // cooling down now, and the sequence before it fails as well
+        assert_eq!(tick(&mut tree, &asset, &mut blackboard, 1.0), Status::Failure);
+
+        blackboard.set_float("health", 5.0);
+        assert_eq!(tick(&mut tree, &asset, &mut blackboard, 1.0), Status::Success);
+    }
+
+    #[test]
+    fn selectors_let_higher_priorities_interrupt() {
+        let asset: BehaviorTreeAsset = ron::from_str(r#"(
+            root: Selector([
+                Sequence([Condition(Below("health", 10.0)), Action("stop")]),
+                Wait(1.0),
+            ]),
+        )"#).unwrap();
+        let mut tree = BehaviorTree::new(Handle::default());
+        let mut blackboard = Blackboard::new();
+        blackboard.set_float("health", 50.0);
+
+        assert_eq!(tick(&mut tree, &asset, &mut blackboard, 0.0), Status::Running);
+
+        blackboard.set_float("health", 5.0);
+        assert_eq!(tick(&mut tree, &asset, &mut blackboard, 0.5), Status::Success);
+
+        // the interrupted wait starts over
+        blackboard.set_float("health", 50.0);
+        assert_eq!(tick(&mut tree, &asset, &mut blackboard, 0.6), Status::Running);
+        assert_eq!(tick(&mut tree, &asset, &mut blackboard, 1.2), Status::Running);
+        assert_eq!(tick(&mut tree, &asset, &mut blackboard, 1.7), Status::Success);
+    }
+
+    #[test]
+    fn parallels_restart_unfinished_children() {
+        let asset: BehaviorTreeAsset = ron::from_str(r#"(
+            root: Parallel(succeed_at: 1, children: [
+                Sequence([Condition(Below("health", 10.0)), Action("stop")]),
+                Wait(1.0),
+            ]),
+        )"#).unwrap();
+        let mut tree = BehaviorTree::new(Handle::default());
+        let mut blackboard = Blackboard::new();
+        blackboard.set_float("health", 50.0);
+
+        assert_eq!(tick(&mut tree, &asset, &mut blackboard, 0.0), Status::Running);
+
+        blackboard.set_float("health", 5.0);
+        assert_eq!(tick(&mut tree, &asset, &mut blackboard, 0.5), Status::Success);
+
+        // entered again, the wait doesn't pick up where it was cut off
+        blackboard.set_float("health", 50.0);
+        assert_eq!(tick(&mut tree, &asset, &mut blackboard, 0.6), Status::Running);
+        assert_eq!(tick(&mut tree, &asset, &mut blackboard, 1.2), Status::Running);
+        assert_eq!(tick(&mut tree, &asset, &mut blackboard, 1.7), Status::Success);
+    }
+}
@@ -0,0 +1,95 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+/// Keys shared between the game and ranger_ai. Anything else is up to whoever uses the blackboard.
+pub mod keys {
+    pub const HEALTH: &str = "health";
+    pub const MAX_HEALTH: &str = "max_health";
+    pub const DISTANCE_TO_TARGET: &str = "distance_to_target";
+    pub const ALLY_COUNT: &str = "ally_count";
+    pub const HAS_PATROL_ROUTE: &str = "has_patrol_route";
+    /// Written by the AI, the game moves the actor towards it
+    pub const MOVE_TO: &str = "move_to";
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlackboardValue {
+    Bool(bool),
+    Float(f32),
+    Vector(Vec3),
+    Entity(Entity),
+}
+
+/// Per-entity scratch space the AI reads its inputs from.
+///
+/// ranger_ai doesn't know about the game's components, so the game copies whatever the AI should
+/// be able to reason about in here. The typed getters return None for missing keys and for keys
+/// holding a different kind of value.
+#[derive(Component, Debug, Default, Clone)]
+pub struct Blackboard(HashMap<String, BlackboardValue>);
+
+impl Blackboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, key: &str, value: BlackboardValue) {
+        self.0.insert(key.to_string(), value);
+    }
+
+    pub fn get(&self, key: &str) -> Option<BlackboardValue> {
+        self.0.get(key).copied()
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.0.remove(key);
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.0.contains_key(key)
+    }
+
+    pub fn set_bool(&mut self, key: &str, value: bool) {
+        self.set(key, BlackboardValue::Bool(value));
+    }
+
+    pub fn set_float(&mut self, key: &str, value: f32) {
+        self.set(key, BlackboardValue::Float(value));
+    }
+
+    pub fn set_vector(&mut self, key: &str, value: Vec3) {
+        self.set(key, BlackboardValue::Vector(value));
+    }
+
+    pub fn set_entity(&mut self, key: &str, value: Entity) {
+        self.set(key, BlackboardValue::Entity(value));
+    }
+
+    pub fn bool(&self, key: &str) -> Option<bool> {
+        match self.get(key)? {
+            BlackboardValue::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn float(&self, key: &str) -> Option<f32> {
+        match self.get(key)? {
+            BlackboardValue::Float(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn vector(&self, key: &str) -> Option<Vec3> {
+        match self.get(key)? {
+            BlackboardValue::Vector(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn entity(&self, key: &str) -> Option<Entity> {
+        match self.get(key)? {
+            BlackboardValue::Entity(value) => Some(value),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+use crate::WalkableGrid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InfluenceLayer {
+    /// Where the player is, and where they can get to quickly
+    PlayerThreat,
+    /// Where the enemies are bunched up
+    AllyDensity,
+    /// Where bullets have been flying recently
+    BulletTraffic,
+}
+
+impl InfluenceLayer {
+    pub const ALL: [InfluenceLayer; 3] = [
+        InfluenceLayer::PlayerThreat,
+        InfluenceLayer::AllyDensity,
+        InfluenceLayer::BulletTraffic,
+    ];
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LayerSettings {
+    /// Fraction of the influence lost per second
+    pub decay: f32,
+    /// Fraction of the influence that carries over into the next cell when spreading
+    pub falloff: f32,
+    /// How much of the spread influence gets blended in per update, 0 doesn't spread at all
+    pub momentum: f32,
+}
+
+impl LayerSettings {
+    fn for_layer(layer: InfluenceLayer) -> Self {
+        match layer {
+            InfluenceLayer::PlayerThreat => Self { decay: 0.5, falloff: 0.7, momentum: 0.6 },
+            InfluenceLayer::AllyDensity => Self { decay: 1.0, falloff: 0.5, momentum: 0.4 },
+            InfluenceLayer::BulletTraffic => Self { decay: 1.5, falloff: 0.3, momentum: 0.3 },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Layer {
+    values: Vec<f32>,
+    settings: LayerSettings,
+}
+
+/// Per-cell influence values on top of the same grid the navmesh is built from.
+///
+/// Sources get stamped in every update, then `update` spreads the influence out into the
+/// surrounding cells and lets the old influence fade. Solid cells don't carry any influence,
+/// so it flows around walls instead of through them.
+#[derive(Resource, Debug, Clone)]
+pub struct InfluenceMap {
+    grid: WalkableGrid,
+    layers: HashMap<InfluenceLayer, Layer>,
+}
+
+impl InfluenceMap {
+    pub fn new(grid: WalkableGrid) -> Self {
+        let layers = InfluenceLayer::ALL.iter()
+            .map(|layer| (*layer, Layer {
+                values: vec![0.0; grid.rows * grid.columns],
+                settings: LayerSettings::for_layer(*layer),
+            }))
+            .collect();
+
+        Self { grid, layers }
+    }
+
+    pub fn settings_mut(&mut self, layer: InfluenceLayer) -> &mut LayerSettings {
+        &mut self.layers.get_mut(&layer).unwrap().settings
+    }
+
+    fn cell(&self, point: Vec2) -> Option<usize> {
+        let column = ((point.x - self.grid.origin.x) / self.grid.cell_size.x).floor();
+        let row = ((self.grid.origin.y - point.y) / self.grid.cell_size.y).floor();
+
+        if !self.grid.is_walkable(row as isize, column as isize) {
+            return None;
+        }
+
+        Some(row as usize * self.grid.columns + column as usize)
+    }
+
+    pub fn cell_centre(&self, index: usize) -> Vec2 {
+        let row = (index / self.grid.columns) as f32;
+        let column = (index % self.grid.columns) as f32;
+
+        Vec2::new(
+            self.grid.origin.x + (column + 0.5) * self.grid.cell_size.x,
+            self.grid.origin.y - (row + 0.5) * self.grid.cell_size.y,
+        )
+    }
+
+    pub fn cell_size(&self) -> Vec2 {
+        self.grid.cell_size
+    }
+
+    /// Puts `amount` of influence at the cell containing `point`, unless it already has more
+    pub fn stamp(&mut self, layer: InfluenceLayer, point: Vec3, amount: f32) {
+        let Some(cell) = self.cell(point.truncate()) else {
+            return;
+        };
+
+        let value = &mut self.layers.get_mut(&layer).unwrap().values[cell];
+        *value = value.max(amount);
+    }
+
+    pub fn value(&self, layer: InfluenceLayer, point: Vec3) -> f32 {
+        self.cell(point.truncate())
+            .map_or(0.0, |cell| self.layers[&layer].values[cell])
+    }
+
+    /// Every cell centre with its value, solid cells included
+    pub fn values(&self, layer: InfluenceLayer) -> impl Iterator<Item = (Vec2, f32)> + '_ {
+        self.layers[&layer].values.iter()
+            .enumerate()
+            .map(|(cell, value)| (self.cell_centre(cell), *value))
+    }
+
+    /// The centre of the walkable cell within `radius` of `point` with the least influence
+    pub fn lowest_near(&self, layer: InfluenceLayer, point: Vec3, radius: f32) -> Option<Vec3> {
+        self.cells_near(layer, point, radius)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(centre, _)| centre.extend(point.z))
+    }
+
+    /// The centre of the walkable cell within `radius` of `point` with the most influence
+    pub fn highest_near(&self, layer: InfluenceLayer, point: Vec3, radius: f32) -> Option<Vec3> {
+        self.cells_near(layer, point, radius)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(centre, _)| centre.extend(point.z))
+    }
+
+    fn cells_near(&self, layer: InfluenceLayer, point: Vec3, radius: f32) -> impl Iterator<Item = (Vec2, f32)> + '_ {
+        let point = point.truncate();
+
+        self.values(layer)
+            .enumerate()
+            .filter(|(cell, _)| self.grid.walkable[*cell])
+            .map(|(_, value)| value)
+            .filter(move |(centre, _)| centre.distance(point) <= radius)
+    }
+
+    /// Spreads every layer one cell further and fades it by `delta_seconds` worth of decay
+    pub fn update(&mut self, delta_seconds: f32) {
+        let grid = &self.grid;
+
+        for layer in self.layers.values_mut() {
+            let settings = layer.settings;
+            let retained = (1.0 - settings.decay * delta_seconds).max(0.0);
+            let previous = layer.values.clone();
+
+            for (cell, value) in layer.values.iter_mut().enumerate() {
+                if !grid.walkable[cell] {
+                    *value = 0.0;
+                    continue;
+                }
+
+                let row = (cell / grid.columns) as isize;
+                let column = (cell % grid.columns) as isize;
+                let mut spread: f32 = 0.0;
+
+                for (row_offset, column_offset) in NEIGHBOURS {
+                    let (neighbour_row, neighbour_column) = (row + row_offset, column + column_offset);
+                    if !grid.is_walkable(neighbour_row, neighbour_column) {
+                        continue;
+                    }
+
+                    let distance = if row_offset != 0 && column_offset != 0 { std::f32::consts::SQRT_2 } else { 1.0 };
+                    let neighbour = previous[neighbour_row as usize * grid.columns + neighbour_column as usize];
+                    spread = spread.max(neighbour * settings.falloff.powf(distance));
+                }
+
+                *value = (previous[cell] + (spread - previous[cell]).max(0.0) * settings.momentum) * retained;
+            }
+        }
+    }
+}
+
+const NEIGHBOURS: [(isize, isize); 8] = [
+    (-1, -1), (-1, 0), (-1, 1),
+    (0, -1), (0, 1),
+    (1, -1), (1, 0), (1, 1),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn influence_spreads_and_fades() {
+        let mut influence = InfluenceMap::new(WalkableGrid {
+            rows: 1,
+            columns: 3,
+            cell_size: Vec2::splat(10.0),
+            origin: Vec2::new(0.0, 10.0),
+            walkable: vec![true, true, true],
+        });
+
+        influence.stamp(InfluenceLayer::PlayerThreat, Vec3::new(5.0, 5.0, 0.0), 1.0);
+        influence.update(0.1);
+
+        let near = influence.value(InfluenceLayer::PlayerThreat, Vec3::new(15.0, 5.0, 0.0));
+        assert!(near > 0.0 && near < 1.0);
+        assert_eq!(influence.lowest_near(InfluenceLayer::PlayerThreat, Vec3::ZERO, 100.0), Some(Vec3::new(25.0, 5.0, 0.0)));
+
+        for _ in 0..100 {
+            influence.update(0.1);
+        }
+        assert!(influence.value(InfluenceLayer::PlayerThreat, Vec3::new(5.0, 5.0, 0.0)) < 0.01);
+    }
+}
@@ -1,31 +1,214 @@
 use bevy::prelude::*;
 
+pub mod avoidance;
+pub mod behavior_tree;
+pub mod blackboard;
+pub mod influence;
+pub mod loader;
+pub mod navmesh;
+pub mod patrol;
+pub mod squad;
+pub mod state_machine;
+pub mod targeting;
+pub mod threat;
+pub mod utility;
+
+pub use avoidance::Avoidance;
+pub use behavior_tree::{BehaviorActions, BehaviorTree, BehaviorTreeAsset};
+pub use blackboard::{Blackboard, BlackboardValue};
+pub use influence::{InfluenceLayer, InfluenceMap};
+pub use loader::RonLoader;
+pub use navmesh::{NavMesh, NavPath, WalkableGrid};
+pub use patrol::{PatrolMode, PatrolRoute, Waypoint};
+pub use squad::{SquadAgent, SquadMember, SquadRole, SquadSettings};
+pub use state_machine::{AiState, StateChanged, StateContext, StateMachine};
+pub use threat::{Taunt, ThreatEvent, ThreatSource, ThreatTable};
+pub use utility::{ActionChosen, UtilityAction, UtilityAi, UtilityDebug};
+
+const DEFAULT_SEARCH_DURATION: f32 = 4.0;
+const DEFAULT_SEARCH_RADIUS: f32 = 60.0;
+// how fast the search point circles the last known position, in radians per second
+const SEARCH_SWEEP_SPEED: f32 = 1.5;
+
+/// What an actor is after, and what it remembers about it.
+///
+/// `point` is only set while the target is actually perceived. Once it's lost, the last known
+/// position and the time it was seen are kept around, so the actor can go look for it for
+/// `search_duration` seconds before giving up.
+///
+/// When the target is an actual entity, `point` follows it around between perception updates.
 #[derive(Component, Debug)]
 pub struct Target {
+    pub entity: Option<Entity>,
     pub point: Option<Vec3>,
+    pub last_known: Option<Vec3>,
+    pub last_seen: f32,
+    pub search_duration: f32,
+    pub search_radius: f32,
 }
 
 impl Target {
     pub fn new(point: Option<Vec3>) -> Self {
-        Target { point }
+        Target {
+            entity: None,
+            point,
+            last_known: point,
+            last_seen: 0.0,
+            search_duration: DEFAULT_SEARCH_DURATION,
+            search_radius: DEFAULT_SEARCH_RADIUS,
+        }
+    }
+
+    pub fn with_search(mut self, duration: f32, radius: f32) -> Self {
+        self.search_duration = duration;
+        self.search_radius = radius;
+        self
     }
 
-    pub fn set_target(&mut self, point: Vec3) {
+    /// `now` is the elapsed time in seconds, as given by `Time::elapsed_seconds`
+    pub fn set_target(&mut self, point: Vec3, now: f32) {
         self.point = Some(point);
+        self.last_known = Some(point);
+        self.last_seen = now;
     }
 
+    /// Same as `set_target`, but `point` keeps following `entity` for as long as it is perceived
+    pub fn set_entity(&mut self, entity: Entity, point: Vec3, now: f32) {
+        self.entity = Some(entity);
+        self.set_target(point, now);
+    }
+
+    /// Loses sight of the target, but keeps the memory of where it was
     pub fn remove_target(&mut self) {
         self.point = None;
     }
 
+    /// Drops the memory as well, after this the actor has nothing to go after
+    pub fn forget(&mut self) {
+        self.entity = None;
+        self.point = None;
+        self.last_known = None;
+    }
+
     pub fn has_target(&self) -> bool {
         self.point.is_some()
     }
+
+    pub fn is_searching(&self, now: f32) -> bool {
+        !self.has_target()
+            && self.last_known.is_some()
+            && now - self.last_seen < self.search_duration
+    }
+
+    /// Where the actor should be heading right now.
+    ///
+    /// While the target is visible that's the target itself. While searching, it's the last known
+    /// position until the actor gets there, after which it sweeps around it in a circle.
+    /// Returns None once there's nothing to look for anymore.
+    pub fn destination(&self, position: Vec3, now: f32) -> Option<Vec3> {
+        if let Some(point) = self.point {
+            return Some(point);
+        }
+
+        if !self.is_searching(now) {
+            return None;
+        }
+
+        let last_known = self.last_known?;
+        if position.distance(last_known) > self.search_radius * 2.0 {
+            return Some(last_known);
+        }
+
+        let angle = (now - self.last_seen) * SEARCH_SWEEP_SPEED;
+        Some(last_known + Vec3::new(angle.cos(), angle.sin(), 0.0) * self.search_radius)
+    }
+}
+
+/// Switches an actor's AI off for as long as it's there. Whatever put it there takes it off again.
+#[derive(Component, Debug, Default)]
+pub struct Stunned;
+
+/// How far and how wide an actor can see. Actors face along their local x axis.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Perception {
+    pub range: f32,
+    /// Full width of the view cone in radians, `TAU` sees all around
+    pub field_of_view: f32,
+}
+
+impl Perception {
+    pub fn new(range: f32, field_of_view: f32) -> Self {
+        Self { range, field_of_view }
+    }
+
+    pub fn facing(transform: &Transform) -> Vec2 {
+        (transform.rotation * Vec3::X).truncate()
+    }
+
+    pub fn can_see(&self, transform: &Transform, point: Vec3) -> bool {
+        let offset = (point - transform.translation).truncate();
+        if offset.length() > self.range {
+            return false;
+        }
+
+        if self.field_of_view >= std::f32::consts::TAU {
+            return true;
+        }
+
+        Self::facing(transform).angle_between(offset).abs() <= self.field_of_view / 2.0
+    }
+}
+
+pub struct AiPlugin;
+
+impl Plugin for AiPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_asset::<BehaviorTreeAsset>()
+            .register_asset_loader(RonLoader::<BehaviorTreeAsset>::new(&["bt.ron"]))
+            .init_resource::<BehaviorActions>()
+            .init_resource::<UtilityDebug>()
+            .init_resource::<SquadSettings>()
+            .add_event::<StateChanged>()
+            .add_event::<ActionChosen>()
+            .add_event::<ThreatEvent>()
+            .add_event::<Taunt>()
+            .add_systems(Update, (
+                state_machine::update_state_machines,
+                behavior_tree::tick_behavior_trees,
+                utility::score_utility_ai,
+                avoidance::avoid_neighbours,
+                squad::form_squads,
+                squad::share_perception
+                    .after(squad::form_squads)
+                    .before(state_machine::update_state_machines),
+                squad::assign_roles.after(squad::share_perception),
+                threat::apply_threat_events,
+                threat::update_threat_tables.after(threat::apply_threat_events),
+                threat::track_targets
+                    .after(squad::share_perception)
+                    .before(state_machine::update_state_machines),
+            ));
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn it_works() {
     }
+
+    #[test]
+    fn target_is_remembered_until_search_runs_out() {
+        let mut target = Target::new(None).with_search(2.0, 10.0);
+        target.set_target(Vec3::new(100.0, 0.0, 0.0), 1.0);
+        target.remove_target();
+
+        assert!(target.is_searching(2.5));
+        assert_eq!(target.destination(Vec3::ZERO, 2.5), Some(Vec3::new(100.0, 0.0, 0.0)));
+        assert!(!target.is_searching(3.5));
+        assert_eq!(target.destination(Vec3::ZERO, 3.5), None);
+    }
 }
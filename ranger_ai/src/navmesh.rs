@@ -0,0 +1,478 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+/// Which cells of a uniform grid can be walked on.
+/// Rows go top to bottom and columns left to right, the same way `world::map::Grid` counts them.
+#[derive(Debug, Clone)]
+pub struct WalkableGrid {
+    pub rows: usize,
+    pub columns: usize,
+    pub cell_size: Vec2,
+    /// The top left corner of the first cell
+    pub origin: Vec2,
+    pub walkable: Vec<bool>,
+}
+
+impl WalkableGrid {
+    pub fn is_walkable(&self, row: isize, column: isize) -> bool {
+        if row < 0 || column < 0 || row as usize >= self.rows || column as usize >= self.columns {
+            return false;
+        }
+
+        self.walkable[row as usize * self.columns + column as usize]
+    }
+
+    // the grid line between two rows or columns, in world space
+    fn x(&self, column: usize) -> f32 {
+        self.origin.x + column as f32 * self.cell_size.x
+    }
+
+    fn y(&self, row: usize) -> f32 {
+        self.origin.y - row as f32 * self.cell_size.y
+    }
+}
+
+/// The crossing from one polygon into another
+#[derive(Debug, Clone, Copy)]
+pub struct Portal {
+    pub to: usize,
+    pub a: Vec2,
+    pub b: Vec2,
+}
+
+impl Portal {
+    fn midpoint(&self) -> Vec2 {
+        (self.a + self.b) / 2.0
+    }
+}
+
+/// The polygons are rectangles, since they're merged together from grid cells.
+/// `min` and `max` are already eroded by the agent radius wherever they border on something solid.
+#[derive(Debug, Clone)]
+pub struct NavPolygon {
+    pub min: Vec2,
+    pub max: Vec2,
+    pub portals: Vec<Portal>,
+}
+
+impl NavPolygon {
+    pub fn centre(&self) -> Vec2 {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        point.x >= self.min.x && point.x <= self.max.x && point.y >= self.min.y && point.y <= self.max.y
+    }
+
+    fn clamp(&self, point: Vec2) -> Vec2 {
+        point.clamp(self.min, self.max.max(self.min))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NavMesh {
+    pub agent_radius: f32,
+    pub polygons: Vec<NavPolygon>,
+}
+
+// a rectangle of cells, end exclusive
+#[derive(Debug, Clone, Copy)]
+struct CellRect {
+    row: usize,
+    column: usize,
+    end_row: usize,
+    end_column: usize,
+}
+
+impl NavMesh {
+    /// Greedily merges walkable cells into rectangles, then connects neighbouring rectangles with
+    /// portals. Anything the agent would have to squeeze through with less than its radius to spare
+    /// on either side gets eroded away.
+    pub fn build(grid: &WalkableGrid, agent_radius: f32) -> Self {
+        let rects = merge_cells(grid);
+        let mut polygons: Vec<NavPolygon> = rects.iter()
+            .map(|rect| erode(grid, rect, agent_radius))
+            .collect();
+
+        for (i, first) in rects.iter().enumerate() {
+            for (j, second) in rects.iter().enumerate().skip(i + 1) {
+                let Some((a, b)) = portal(grid, first, second, agent_radius) else {
+                    continue;
+                };
+
+                polygons[i].portals.push(Portal { to: j, a, b });
+                polygons[j].portals.push(Portal { to: i, a, b });
+            }
+        }
+
+        Self { agent_radius, polygons }
+    }
+
+    pub fn find_polygon(&self, point: Vec2) -> Option<usize> {
+        self.polygons.iter().position(|polygon| polygon.contains(point))
+    }
+
+    /// Falls back to whatever polygon is closest, for points in eroded space or inside walls
+    fn nearest_polygon(&self, point: Vec2) -> Option<usize> {
+        self.find_polygon(point).or_else(|| {
+            self.polygons.iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| {
+                    a.clamp(point).distance_squared(point).total_cmp(&b.clamp(point).distance_squared(point))
+                })
+                .map(|(index, _)| index)
+        })
+    }
+
+    /// A* over the polygons followed by the funnel algorithm, so the path only bends at corners.
+    /// The returned path starts after `from` and ends at `to`, pulled onto the mesh if needed.
+    pub fn find_path(&self, from: Vec3, to: Vec3) -> Option<Vec<Vec3>> {
+        let start = self.nearest_polygon(from.truncate())?;
+        let goal = self.nearest_polygon(to.truncate())?;
+        let destination = self.polygons[goal].clamp(to.truncate());
+
+        let corridor = self.corridor(start, goal, destination)?;
+
+        let mut portals = vec![(from.truncate(), from.truncate())];
+        for window in corridor.windows(2) {
+            let portal = self.polygons[window[0]].portals.iter()
+                .find(|portal| portal.to == window[1])?;
+            portals.push(self.orient(window[0], window[1], portal));
+        }
+        portals.push((destination, destination));
+
+        Some(funnel(&portals).into_iter()
+            .skip(1)
+            .map(|point| point.extend(from.z))
+            .collect())
+    }
+
+    fn corridor(&self, start: usize, goal: usize, destination: Vec2) -> Option<Vec<usize>> {
+        let mut open = vec![start];
+        let mut came_from: HashMap<usize, usize> = HashMap::new();
+        let mut cost: HashMap<usize, f32> = HashMap::new();
+        cost.insert(start, 0.0);
+
+        while !open.is_empty() {
+            let (position, &current) = open.iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| {
+                    let a_score = cost[*a] + self.polygons[**a].centre().distance(destination);
+                    let b_score = cost[*b] + self.polygons[**b].centre().distance(destination);
+                    a_score.total_cmp(&b_score)
+                })?;
+            open.swap_remove(position);
+
+            if current == goal {
+                let mut corridor = vec![goal];
+                while let Some(previous) = came_from.get(corridor.last()?) {
+                    corridor.push(*previous);
+                }
+                corridor.reverse();
+                return Some(corridor);
+            }
+
+            let centre = self.polygons[current].centre();
+            for portal in self.polygons[current].portals.iter() {
+                let next_centre = self.polygons[portal.to].centre();
+                let next_cost = cost[&current]
+                    + centre.distance(portal.midpoint())
+                    + portal.midpoint().distance(next_centre);
+
+                if cost.get(&portal.to).is_some_and(|known| *known <= next_cost) {
+                    continue;
+                }
+
+                cost.insert(portal.to, next_cost);
+                came_from.insert(portal.to, current);
+                if !open.contains(&portal.to) {
+                    open.push(portal.to);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Returns the portal as (left, right), seen when walking from `from` into `to`.
+    /// The walking direction is taken straight across the portal, the centres of long polygons
+    /// can be way off to the side.
+    fn orient(&self, from: usize, to: usize, portal: &Portal) -> (Vec2, Vec2) {
+        let mut direction = (portal.b - portal.a).perp();
+        if direction.dot(self.polygons[to].centre() - self.polygons[from].centre()) < 0.0 {
+            direction = -direction;
+        }
+
+        if direction.perp_dot(portal.a - portal.midpoint()) > 0.0 {
+            (portal.a, portal.b)
+        } else {
+            (portal.b, portal.a)
+        }
+    }
+}
+
+fn merge_cells(grid: &WalkableGrid) -> Vec<CellRect> {
+    let mut taken = vec![false; grid.rows * grid.columns];
+    let mut rects = vec![];
+    let free = |taken: &Vec<bool>, row: usize, column: usize| {
+        grid.walkable[row * grid.columns + column] && !taken[row * grid.columns + column]
+    };
+
+    for row in 0..grid.rows {
+        for column in 0..grid.columns {
+            if !free(&taken, row, column) {
+                continue;
+            }
+
+            let mut end_column = column + 1;
+            while end_column < grid.columns && free(&taken, row, end_column) {
+                end_column += 1;
+            }
+
+            let mut end_row = row + 1;
+            while end_row < grid.rows && (column..end_column).all(|c| free(&taken, end_row, c)) {
+                end_row += 1;
+            }
+
+            for r in row..end_row {
+                for c in column..end_column {
+                    taken[r * grid.columns + c] = true;
+                }
+            }
+
+            rects.push(CellRect { row, column, end_row, end_column });
+        }
+    }
+
+    rects
+}
+
+fn erode(grid: &WalkableGrid, rect: &CellRect, radius: f32) -> NavPolygon {
+    let blocked = |row: isize, column: isize| !grid.is_walkable(row, column);
+    let rows = rect.row as isize..rect.end_row as isize;
+    let columns = rect.column as isize..rect.end_column as isize;
+
+    let mut min = Vec2::new(grid.x(rect.column), grid.y(rect.end_row));
+    let mut max = Vec2::new(grid.x(rect.end_column), grid.y(rect.row));
+
+    if rows.clone().any(|row| blocked(row, rect.column as isize - 1)) { min.x += radius; }
+    if rows.clone().any(|row| blocked(row, rect.end_column as isize)) { max.x -= radius; }
+    if columns.clone().any(|column| blocked(rect.end_row as isize, column)) { min.y += radius; }
+    if columns.clone().any(|column| blocked(rect.row as isize - 1, column)) { max.y -= radius; }
+
+    NavPolygon { min, max, portals: vec![] }
+}
+
+/// The shared edge of two rectangles, pulled in from any corner it ends at
+fn portal(grid: &WalkableGrid, first: &CellRect, second: &CellRect, radius: f32) -> Option<(Vec2, Vec2)> {
+    let blocked = |row: isize, column: isize| !grid.is_walkable(row, column);
+
+    // vertical edge, one to the left of the other
+    let vertical = if first.end_column == second.column {
+        Some(first.end_column)
+    } else if second.end_column == first.column {
+        Some(second.end_column)
+    } else {
+        None
+    };
+
+    if let Some(column) = vertical {
+        let start = first.row.max(second.row);
+        let end = first.end_row.min(second.end_row);
+        if start >= end {
+            return None;
+        }
+
+        let column = column as isize;
+        let corner = |row: isize| blocked(row, column - 1) || blocked(row, column);
+        let top = grid.y(start) - if corner(start as isize - 1) { radius } else { 0.0 };
+        let bottom = grid.y(end) + if corner(end as isize) { radius } else { 0.0 };
+        if top <= bottom {
+            return None;
+        }
+
+        let x = grid.x(column as usize);
+        return Some((Vec2::new(x, top), Vec2::new(x, bottom)));
+    }
+
+    // horizontal edge, one above the other
+    let row = if first.end_row == second.row {
+        first.end_row
+    } else if second.end_row == first.row {
+        second.end_row
+    } else {
+        return None;
+    };
+
+    let start = first.column.max(second.column);
+    let end = first.end_column.min(second.end_column);
+    if start >= end {
+        return None;
+    }
+
+    let row = row as isize;
+    let corner = |column: isize| blocked(row - 1, column) || blocked(row, column);
+    let left = grid.x(start) + if corner(start as isize - 1) { radius } else { 0.0 };
+    let right = grid.x(end) - if corner(end as isize) { radius } else { 0.0 };
+    if left >= right {
+        return None;
+    }
+
+    let y = grid.y(row as usize);
+    Some((Vec2::new(left, y), Vec2::new(right, y)))
+}
+
+// twice the signed area of the triangle, positive when c is to the right of a -> b
+fn triangle_area(a: Vec2, b: Vec2, c: Vec2) -> f32 {
+    (c.x - a.x) * (b.y - a.y) - (b.x - a.x) * (c.y - a.y)
+}
+
+/// The simple stupid funnel algorithm
+/// http://digestingduck.blogspot.com/2010/03/simple-stupid-funnel-algorithm.html
+///
+/// Takes the portals as (left, right) pairs, with the start and end as portals of zero width.
+pub fn funnel(portals: &[(Vec2, Vec2)]) -> Vec<Vec2> {
+    let Some(&(start, _)) = portals.first() else {
+        return vec![];
+    };
+
+    let mut path = vec![start];
+    let mut apex = start;
+    let (mut funnel_left, mut funnel_right) = (start, start);
+    let (mut left_index, mut right_index) = (0, 0);
+
+    let mut i = 1;
+    while i < portals.len() {
+        let (left, right) = portals[i];
+
+        // try to narrow the right side of the funnel
+        if triangle_area(apex, funnel_right, right) <= 0.0 {
+            if apex == funnel_right || triangle_area(apex, funnel_left, right) > 0.0 {
+                funnel_right = right;
+                right_index = i;
+            } else {
+                // crossed over the left side, so its corner becomes the new apex
+                path.push(funnel_left);
+                apex = funnel_left;
+                let apex_index = left_index;
+                (funnel_left, funnel_right) = (apex, apex);
+                (left_index, right_index) = (apex_index, apex_index);
+                i = apex_index + 1;
+                continue;
+            }
+        }
+
+        // and the left side
+        if triangle_area(apex, funnel_left, left) >= 0.0 {
+            if apex == funnel_left || triangle_area(apex, funnel_right, left) < 0.0 {
+                funnel_left = left;
+                left_index = i;
+            } else {
+                path.push(funnel_right);
+                apex = funnel_right;
+                let apex_index = right_index;
+                (funnel_left, funnel_right) = (apex, apex);
+                (left_index, right_index) = (apex_index, apex_index);
+                i = apex_index + 1;
+                continue;
+            }
+        }
+
+        i += 1;
+    }
+
+    if let Some(&(end, _)) = portals.last() {
+        if path.last() != Some(&end) {
+            path.push(end);
+        }
+    }
+
+    path
+}
+
+/// Where an agent is going along the navmesh
+#[derive(Component, Debug, Default, Clone)]
+pub struct NavPath {
+    pub waypoints: Vec<Vec3>,
+    pub goal: Option<Vec3>,
+    index: usize,
+}
+
+// how far the goal can move before the path gets planned again
+const REPLAN_DISTANCE: f32 = 30.0;
+const WAYPOINT_REACHED: f32 = 6.0;
+
+impl NavPath {
+    pub fn needs_replan(&self, goal: Vec3) -> bool {
+        self.goal.is_none_or(|old_goal| old_goal.distance(goal) > REPLAN_DISTANCE)
+    }
+
+    pub fn set(&mut self, goal: Vec3, waypoints: Vec<Vec3>) {
+        self.goal = Some(goal);
+        self.waypoints = waypoints;
+        self.index = 0;
+    }
+
+    pub fn clear(&mut self) {
+        self.goal = None;
+        self.waypoints.clear();
+        self.index = 0;
+    }
+
+    /// The waypoint to steer towards from `position`, skipping the ones already reached
+    pub fn next(&mut self, position: Vec3) -> Option<Vec3> {
+        while self.index + 1 < self.waypoints.len()
+            && position.distance(self.waypoints[self.index]) <= WAYPOINT_REACHED
+        {
+            self.index += 1;
+        }
+
+        self.waypoints.get(self.index).copied()
+    }
+
+    pub fn remaining(&self) -> &[Vec3] {
+        &self.waypoints[self.index.min(self.waypoints.len())..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // . . .
+    // . # .
+    // . # .
+    fn pillar() -> WalkableGrid {
+        WalkableGrid {
+            rows: 3,
+            columns: 3,
+            cell_size: Vec2::splat(10.0),
+            origin: Vec2::new(0.0, 30.0),
+            walkable: vec![
+                true, true, true,
+                true, false, true,
+                true, false, true,
+            ],
+        }
+    }
+
+    #[test]
+    fn paths_bend_only_at_the_eroded_corners() {
+        let navmesh = NavMesh::build(&pillar(), 2.0);
+        let path = navmesh.find_path(Vec3::new(5.0, 5.0, 0.0), Vec3::new(25.0, 5.0, 0.0)).unwrap();
+
+        assert_eq!(path, vec![
+            Vec3::new(8.0, 20.0, 0.0),
+            Vec3::new(22.0, 20.0, 0.0),
+            Vec3::new(25.0, 5.0, 0.0),
+        ]);
+    }
+
+    #[test]
+    fn straight_lines_stay_straight() {
+        let navmesh = NavMesh::build(&pillar(), 2.0);
+        let path = navmesh.find_path(Vec3::new(3.0, 25.0, 0.0), Vec3::new(27.0, 25.0, 0.0)).unwrap();
+
+        assert_eq!(path, vec![Vec3::new(27.0, 25.0, 0.0)]);
+    }
+}
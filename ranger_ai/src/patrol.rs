@@ -0,0 +1,129 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+// how close counts as being at a waypoint
+const WAYPOINT_TOLERANCE: f32 = 8.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum PatrolMode {
+    /// Goes back to the first waypoint after the last one
+    #[default]
+    Loop,
+    /// Walks the route backwards after reaching the end, then forwards again
+    PingPong,
+    /// Stops at the last waypoint
+    Once,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Waypoint {
+    pub point: Vec3,
+    /// Seconds to stand around after arriving
+    pub wait: f32,
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct PatrolRoute {
+    pub waypoints: Vec<Waypoint>,
+    pub mode: PatrolMode,
+    current: usize,
+    backwards: bool,
+    arrived_at: Option<f32>,
+    finished: bool,
+}
+
+impl PatrolRoute {
+    pub fn new(waypoints: Vec<Waypoint>, mode: PatrolMode) -> Self {
+        Self {
+            finished: waypoints.is_empty(),
+            waypoints,
+            mode,
+            current: 0,
+            backwards: false,
+            arrived_at: None,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn current_waypoint(&self) -> Option<&Waypoint> {
+        if self.finished {
+            return None;
+        }
+
+        self.waypoints.get(self.current)
+    }
+
+    /// Where to walk to from `position`. Advances the route once the current waypoint has been
+    /// reached and waited at. Returns None while waiting, and for good once a `Once` route is done.
+    pub fn update(&mut self, position: Vec3, now: f32) -> Option<Vec3> {
+        let waypoint = *self.current_waypoint()?;
+
+        if position.distance(waypoint.point) > WAYPOINT_TOLERANCE {
+            self.arrived_at = None;
+            return Some(waypoint.point);
+        }
+
+        let arrived_at = *self.arrived_at.get_or_insert(now);
+        if now - arrived_at < waypoint.wait {
+            return None;
+        }
+
+        self.arrived_at = None;
+        self.advance();
+        self.current_waypoint().map(|waypoint| waypoint.point)
+    }
+
+    fn advance(&mut self) {
+        let last = self.waypoints.len() - 1;
+
+        match self.mode {
+            PatrolMode::Loop => self.current = if self.current == last { 0 } else { self.current + 1 },
+            PatrolMode::Once => {
+                if self.current == last {
+                    self.finished = true;
+                } else {
+                    self.current += 1;
+                }
+            },
+            PatrolMode::PingPong => {
+                if last == 0 {
+                    return;
+                }
+
+                if self.current == last {
+                    self.backwards = true;
+                } else if self.current == 0 {
+                    self.backwards = false;
+                }
+
+                self.current = if self.backwards { self.current - 1 } else { self.current + 1 };
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ping_pong_turns_around_at_the_ends() {
+        let waypoints = (0..3)
+            .map(|i| Waypoint { point: Vec3::new(i as f32 * 100.0, 0.0, 0.0), wait: 0.0 })
+            .collect();
+        let mut route = PatrolRoute::new(waypoints, PatrolMode::PingPong);
+
+        let visited: Vec<f32> = (0..5)
+            .map(|_| {
+                let point = route.current_waypoint().unwrap().point;
+                route.update(point, 0.0);
+                point.x
+            })
+            .collect();
+
+        assert_eq!(visited, vec![0.0, 100.0, 200.0, 100.0, 0.0]);
+    }
+}
@@ -0,0 +1,212 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+use crate::Target;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SquadRole {
+    /// Goes straight for the target
+    Rush,
+    /// Holds at a distance and keeps the target busy
+    PinDown,
+    FlankLeft,
+    FlankRight,
+}
+
+// the order roles get handed out in, closest to the target first
+const ROLES: [SquadRole; 4] = [SquadRole::Rush, SquadRole::PinDown, SquadRole::FlankLeft, SquadRole::FlankRight];
+// how close a flanker has to get to its spot before it goes in
+const FLANK_TOLERANCE: f32 = 20.0;
+
+/// Lets an actor team up with other squad agents nearby
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct SquadAgent;
+
+/// Given to squad agents that found company. Squads get reformed every so often, so the id is
+/// only good for telling who's together right now.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct SquadMember {
+    pub squad: usize,
+    pub role: SquadRole,
+    /// Where the role wants the member to be, if the squad is after something
+    pub goal: Option<Vec3>,
+}
+
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct SquadSettings {
+    /// Agents closer than this to any member join the squad
+    pub join_distance: f32,
+    /// Seconds between reforming the squads
+    pub reform_interval: f32,
+    pub flank_distance: f32,
+    pub pin_down_distance: f32,
+}
+
+impl Default for SquadSettings {
+    fn default() -> Self {
+        Self {
+            join_distance: 200.0,
+            reform_interval: 1.0,
+            flank_distance: 150.0,
+            pin_down_distance: 220.0,
+        }
+    }
+}
+
+/// Groups squad agents that are within `join_distance` of each other, directly or through
+/// other agents. Loners don't get a squad.
+pub fn form_squads(
+    agent_query: Query<(Entity, &Transform, Option<&SquadMember>), With<SquadAgent>>,
+    mut commands: Commands,
+    res_squad_settings: Res<SquadSettings>,
+    res_time: Res<Time>,
+    mut last_formed: Local<Option<f32>>,
+) {
+    let now = res_time.elapsed_seconds();
+    if last_formed.is_some_and(|last_formed| now - last_formed < res_squad_settings.reform_interval) {
+        return;
+    }
+    *last_formed = Some(now);
+
+    let agents: Vec<(Entity, Vec3, bool)> = agent_query.iter()
+        .map(|(entity, transform, member)| (entity, transform.translation, member.is_some()))
+        .collect();
+    let mut squads: Vec<Option<usize>> = vec![None; agents.len()];
+    let mut squad_count = 0;
+
+    for first in 0..agents.len() {
+        if squads[first].is_some() {
+            continue;
+        }
+
+        // flood fill everyone reachable from here
+        squads[first] = Some(squad_count);
+        let mut open = vec![first];
+        while let Some(current) = open.pop() {
+            for next in 0..agents.len() {
+                if squads[next].is_some() || agents[current].1.distance(agents[next].1) > res_squad_settings.join_distance {
+                    continue;
+                }
+
+                squads[next] = Some(squad_count);
+                open.push(next);
+            }
+        }
+        squad_count += 1;
+    }
+
+    let mut sizes = vec![0; squad_count];
+    for squad in squads.iter().flatten() {
+        sizes[*squad] += 1;
+    }
+
+    for ((entity, _, was_member), squad) in agents.iter().zip(squads) {
+        let squad = squad.unwrap();
+
+        if sizes[squad] < 2 {
+            if *was_member {
+                commands.entity(*entity).remove::<SquadMember>();
+            }
+            continue;
+        }
+
+        commands.entity(*entity).insert(SquadMember { squad, role: SquadRole::Rush, goal: None });
+    }
+}
+
+/// Whatever one member sees, the whole squad knows about
+pub fn share_perception(
+    mut member_query: Query<(&SquadMember, &mut Target)>,
+) {
+    let mut sightings: HashMap<usize, (Option<Entity>, Vec3, f32)> = HashMap::new();
+    for (member, target) in member_query.iter() {
+        let Some(point) = target.point else {
+            continue;
+        };
+
+        sightings.insert(member.squad, (target.entity, point, target.last_seen));
+    }
+
+    for (member, mut target) in member_query.iter_mut() {
+        if target.has_target() {
+            continue;
+        }
+
+        match sightings.get(&member.squad) {
+            Some((Some(entity), point, seen)) => target.set_entity(*entity, *point, *seen),
+            Some((None, point, seen)) => target.set_target(*point, *seen),
+            None => {},
+        }
+    }
+}
+
+/// Hands out roles by distance to the target, and turns them into goals around it
+pub fn assign_roles(
+    mut member_query: Query<(&Transform, &Target, &mut SquadMember)>,
+    res_squad_settings: Res<SquadSettings>,
+) {
+    let mut squads: HashMap<usize, Vec<(f32, Vec3)>> = HashMap::new();
+    for (transform, target, member) in member_query.iter() {
+        let Some(point) = target.point else {
+            continue;
+        };
+
+        squads.entry(member.squad).or_default().push((transform.translation.distance(point), transform.translation));
+    }
+
+    for (transform, target, mut member) in member_query.iter_mut() {
+        let (Some(point), Some(squad)) = (target.point, squads.get(&member.squad)) else {
+            member.goal = None;
+            continue;
+        };
+
+        let distance = transform.translation.distance(point);
+        let rank = squad.iter().filter(|(other, _)| *other < distance).count();
+        member.role = ROLES[rank % ROLES.len()];
+
+        // flanking is relative to the side the squad comes from
+        let centre = squad.iter().map(|(_, position)| *position).sum::<Vec3>() / squad.len() as f32;
+        let approach = (point - centre).truncate();
+
+        member.goal = Some(role_goal(member.role, transform.translation, point, approach, &res_squad_settings));
+    }
+}
+
+/// Where `role` wants a member at `position` to be, for a squad coming at `target` from `approach`
+pub fn role_goal(role: SquadRole, position: Vec3, target: Vec3, approach: Vec2, settings: &SquadSettings) -> Vec3 {
+    let left = approach.normalize_or_zero().perp().extend(0.0);
+    let flank = match role {
+        SquadRole::Rush => return target,
+        SquadRole::PinDown => {
+            let away = (position - target).normalize_or_zero();
+            return target + away * settings.pin_down_distance;
+        },
+        SquadRole::FlankLeft => target + left * settings.flank_distance,
+        SquadRole::FlankRight => target - left * settings.flank_distance,
+    };
+
+    // once around the side, go in
+    match position.distance(flank) <= FLANK_TOLERANCE || position.distance(target) < settings.flank_distance {
+        true => target,
+        false => flank,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flankers_go_around_opposite_sides() {
+        let settings = SquadSettings::default();
+        let position = Vec3::new(0.0, -500.0, 0.0);
+        let approach = Vec2::Y;
+
+        let left = role_goal(SquadRole::FlankLeft, position, Vec3::ZERO, approach, &settings);
+        let right = role_goal(SquadRole::FlankRight, position, Vec3::ZERO, approach, &settings);
+
+        assert_eq!(left, Vec3::new(-settings.flank_distance, 0.0, 0.0));
+        assert_eq!(right, Vec3::new(settings.flank_distance, 0.0, 0.0));
+        assert_eq!(role_goal(SquadRole::FlankLeft, left, Vec3::ZERO, approach, &settings), Vec3::ZERO);
+    }
+}
@@ -0,0 +1,163 @@
+use bevy::prelude::*;
+use bevy::ecs::system::EntityCommands;
+
+use crate::{Blackboard, Target};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AiState {
+    Idle,
+    Patrol,
+    Search,
+    Chase,
+    Attack,
+    Flee,
+    Dead,
+}
+
+/// Everything a transition condition gets to look at
+pub struct StateContext<'a> {
+    pub position: Vec3,
+    pub target: &'a Target,
+    pub blackboard: &'a Blackboard,
+    pub time_in_state: f32,
+    pub now: f32,
+}
+
+pub type Condition = fn(&StateContext) -> bool;
+/// Hooks can't look at the world, but they can add and remove components on the entity,
+/// which is usually all that's needed when entering or leaving a state.
+pub type Hook = fn(&mut EntityCommands);
+
+struct Transition {
+    // None means the transition applies from any state
+    from: Option<AiState>,
+    to: AiState,
+    condition: Condition,
+}
+
+/// A state machine driving an AI actor.
+///
+/// Transitions are checked in the order they were added, the first one whose condition holds wins.
+/// Only one transition happens per update. The systems doing the actual work are expected to
+/// filter on the current state with `StateMachine::is`.
+///
+/// ```ignore
+/// StateMachine::new(AiState::Idle)
+///     .transition(AiState::Idle, AiState::Chase, |context| context.target.has_target())
+///     .any_transition(AiState::Dead, is_dead)
+///     .on_enter(AiState::Dead, |entity| { entity.remove::<Path>(); })
+/// ```
+#[derive(Component)]
+pub struct StateMachine {
+    state: AiState,
+    entered_at: f32,
+    transitions: Vec<Transition>,
+    on_enter: Vec<(AiState, Hook)>,
+    on_exit: Vec<(AiState, Hook)>,
+}
+
+impl StateMachine {
+    pub fn new(initial: AiState) -> Self {
+        Self {
+            state: initial,
+            entered_at: 0.0,
+            transitions: vec![],
+            on_enter: vec![],
+            on_exit: vec![],
+        }
+    }
+
+    pub fn transition(mut self, from: AiState, to: AiState, condition: Condition) -> Self {
+        self.transitions.push(Transition { from: Some(from), to, condition });
+        self
+    }
+
+    pub fn any_transition(mut self, to: AiState, condition: Condition) -> Self {
+        self.transitions.push(Transition { from: None, to, condition });
+        self
+    }
+
+    pub fn on_enter(mut self, state: AiState, hook: Hook) -> Self {
+        self.on_enter.push((state, hook));
+        self
+    }
+
+    pub fn on_exit(mut self, state: AiState, hook: Hook) -> Self {
+        self.on_exit.push((state, hook));
+        self
+    }
+
+    pub fn state(&self) -> AiState {
+        self.state
+    }
+
+    pub fn is(&self, state: AiState) -> bool {
+        self.state == state
+    }
+
+    pub fn time_in_state(&self, now: f32) -> f32 {
+        now - self.entered_at
+    }
+
+    fn next_state(&self, context: &StateContext) -> Option<AiState> {
+        self.transitions.iter()
+            .filter(|transition| transition.to != self.state)
+            .filter(|transition| transition.from.is_none_or(|from| from == self.state))
+            .find(|transition| (transition.condition)(context))
+            .map(|transition| transition.to)
+    }
+
+    /// Switches over right away, running the hooks as if a transition happened.
+    /// Returns the previous state.
+    pub fn force(&mut self, state: AiState, now: f32, entity: &mut EntityCommands) -> AiState {
+        let previous = self.state;
+
+        for (_, hook) in self.on_exit.iter().filter(|(hook_state, _)| *hook_state == previous) {
+            hook(entity);
+        }
+
+        self.state = state;
+        self.entered_at = now;
+
+        for (_, hook) in self.on_enter.iter().filter(|(hook_state, _)| *hook_state == state) {
+            hook(entity);
+        }
+
+        previous
+    }
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct StateChanged {
+    pub entity: Entity,
+    pub from: AiState,
+    pub to: AiState,
+}
+
+#[allow(clippy::type_complexity)]
+pub fn update_state_machines(
+    mut machine_query: Query<(Entity, &mut StateMachine, &Transform, &Target, Option<&Blackboard>), Without<crate::Stunned>>,
+    mut commands: Commands,
+    mut state_changed_events: EventWriter<StateChanged>,
+    res_time: Res<Time>,
+) {
+    let now = res_time.elapsed_seconds();
+    let empty_blackboard = Blackboard::default();
+
+    for (entity, mut machine, transform, target, blackboard) in machine_query.iter_mut() {
+        let context = StateContext {
+            position: transform.translation,
+            target,
+            blackboard: blackboard.unwrap_or(&empty_blackboard),
+            time_in_state: machine.time_in_state(now),
+            now,
+        };
+
+        let Some(next) = machine.next_state(&context) else {
+            continue;
+        };
+
+        let from = machine.force(next, now, &mut commands.entity(entity));
+        state_changed_events.send(StateChanged { entity, from, to: next });
+    }
+}
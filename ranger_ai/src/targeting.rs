@@ -0,0 +1,52 @@
+use bevy::prelude::*;
+
+const EPSILON: f32 = 0.00001;
+
+/// Where to aim from `origin` so a projectile flying at `projectile_speed` meets a target at
+/// `target` that keeps moving with `target_velocity`. Aims straight at the target when the
+/// projectile can't catch up.
+pub fn lead_target(origin: Vec3, target: Vec3, target_velocity: Vec3, projectile_speed: f32) -> Vec3 {
+    let offset = target - origin;
+
+    // |offset + velocity * t| = speed * t, solved for the earliest t
+    let a = target_velocity.length_squared() - projectile_speed * projectile_speed;
+    let b = 2.0 * offset.dot(target_velocity);
+    let c = offset.length_squared();
+
+    let time = if a.abs() <= EPSILON {
+        // exactly as fast as the projectile, which makes it linear
+        if b.abs() <= EPSILON { None } else { Some(-c / b) }
+    } else {
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            None
+        } else {
+            let root = discriminant.sqrt();
+            [(-b - root) / (2.0 * a), (-b + root) / (2.0 * a)].into_iter()
+                .filter(|time| *time > 0.0)
+                .min_by(|a, b| a.total_cmp(b))
+        }
+    };
+
+    match time.filter(|time| *time > 0.0) {
+        Some(time) => target + target_velocity * time,
+        None => target,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leads_a_crossing_target() {
+        let target = Vec3::new(300.0, 0.0, 0.0);
+        let velocity = Vec3::new(0.0, 100.0, 0.0);
+
+        let aim = lead_target(Vec3::ZERO, target, velocity, 500.0);
+        let time = aim.length() / 500.0;
+
+        assert!((target + velocity * time).distance(aim) < 0.01);
+        assert_eq!(lead_target(Vec3::ZERO, target, Vec3::ZERO, 500.0), target);
+    }
+}
@@ -0,0 +1,224 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+use crate::Target;
+
+// threat below this is as good as none, and gets dropped from the table
+const MIN_THREAT: f32 = 0.01;
+
+/// Marks something enemies can pick as a target: players, turrets, decoys
+#[derive(Component, Debug, Clone, Copy)]
+pub struct ThreatSource {
+    /// Scales the proximity threat, decoys want this high
+    pub multiplier: f32,
+}
+
+impl Default for ThreatSource {
+    fn default() -> Self {
+        Self { multiplier: 1.0 }
+    }
+}
+
+/// Who an actor is angry at, and how much.
+///
+/// Threat builds up from damage taken, from threat sources being close by and from taunts, and
+/// fades away over time. The actor goes after whoever has the most threat, but only switches away
+/// from its current pick once somebody else is ahead by `switch_margin` and the current pick has
+/// been held for at least `min_hold` seconds. Otherwise two sources at about the same threat would
+/// swap places every frame.
+#[derive(Component, Debug, Clone)]
+pub struct ThreatTable {
+    /// Threat sources further away than this don't add any proximity threat
+    pub proximity_range: f32,
+    /// Threat per second from a source right on top of the actor, falls off linearly with range
+    pub proximity_threat: f32,
+    /// Fraction of the threat lost per second
+    pub decay: f32,
+    /// How far ahead somebody has to be to steal the target, 0.25 means 25% more threat
+    pub switch_margin: f32,
+    pub min_hold: f32,
+    threat: HashMap<Entity, f32>,
+    current: Option<Entity>,
+    switched_at: f32,
+}
+
+impl ThreatTable {
+    pub fn new(proximity_range: f32) -> Self {
+        Self {
+            proximity_range,
+            proximity_threat: 10.0,
+            decay: 0.2,
+            switch_margin: 0.25,
+            min_hold: 1.0,
+            threat: HashMap::new(),
+            current: None,
+            switched_at: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, source: Entity, amount: f32) {
+        *self.threat.entry(source).or_default() += amount;
+    }
+
+    pub fn threat(&self, source: Entity) -> f32 {
+        self.threat.get(&source).copied().unwrap_or(0.0)
+    }
+
+    pub fn remove(&mut self, source: Entity) {
+        self.threat.remove(&source);
+        if self.current == Some(source) {
+            self.current = None;
+        }
+    }
+
+    /// Whoever the actor is after right now
+    pub fn current(&self) -> Option<Entity> {
+        self.current
+    }
+
+    /// Fades the threat by `delta_seconds` worth of decay and drops whatever is left with nothing
+    pub fn decay(&mut self, delta_seconds: f32) {
+        let retained = (1.0 - self.decay * delta_seconds).max(0.0);
+        self.threat.values_mut().for_each(|threat| *threat *= retained);
+        self.threat.retain(|_, threat| *threat >= MIN_THREAT);
+
+        if self.current.is_some_and(|current| !self.threat.contains_key(&current)) {
+            self.current = None;
+        }
+    }
+
+    /// Picks the target for this update, `now` is the elapsed time in seconds
+    pub fn choose(&mut self, now: f32) -> Option<Entity> {
+        let Some((&highest, &highest_threat)) = self.threat.iter().max_by(|(_, a), (_, b)| a.total_cmp(b)) else {
+            self.current = None;
+            return None;
+        };
+
+        let keep_current = self.current.is_some_and(|current| {
+            now - self.switched_at < self.min_hold
+                || highest_threat <= self.threat(current) * (1.0 + self.switch_margin)
+        });
+
+        if !keep_current && self.current != Some(highest) {
+            self.current = Some(highest);
+            self.switched_at = now;
+        }
+
+        self.current
+    }
+}
+
+/// Threat for `entity`'s table, usually from `source` hurting it
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ThreatEvent {
+    pub entity: Entity,
+    pub source: Entity,
+    pub amount: f32,
+}
+
+/// Adds `amount` of threat towards `source` to every table within `radius` of it
+#[derive(Event, Debug, Clone, Copy)]
+pub struct Taunt {
+    pub source: Entity,
+    pub radius: f32,
+    pub amount: f32,
+}
+
+pub fn apply_threat_events(
+    mut table_query: Query<(&mut ThreatTable, &Transform)>,
+    source_query: Query<&Transform, With<ThreatSource>>,
+    mut threat_events: EventReader<ThreatEvent>,
+    mut taunts: EventReader<Taunt>,
+) {
+    for event in threat_events.read() {
+        if let Ok((mut table, _)) = table_query.get_mut(event.entity) {
+            table.add(event.source, event.amount);
+        }
+    }
+
+    for taunt in taunts.read() {
+        let Ok(source_transform) = source_query.get(taunt.source) else {
+            continue;
+        };
+
+        for (mut table, transform) in table_query.iter_mut() {
+            if transform.translation.distance(source_transform.translation) <= taunt.radius {
+                table.add(taunt.source, taunt.amount);
+            }
+        }
+    }
+}
+
+/// Adds proximity threat, forgets sources that are gone and picks every table's target
+pub fn update_threat_tables(
+    mut table_query: Query<(&mut ThreatTable, &Transform)>,
+    source_query: Query<(Entity, &ThreatSource, &Transform)>,
+    res_time: Res<Time>,
+) {
+    let delta_seconds = res_time.delta_seconds();
+    let now = res_time.elapsed_seconds();
+
+    for (mut table, transform) in table_query.iter_mut() {
+        for (source, threat_source, source_transform) in source_query.iter() {
+            let distance = transform.translation.distance(source_transform.translation);
+            if distance > table.proximity_range {
+                continue;
+            }
+
+            let closeness = 1.0 - distance / table.proximity_range;
+            let amount = table.proximity_threat * threat_source.multiplier * closeness * delta_seconds;
+            table.add(source, amount);
+        }
+
+        let gone: Vec<Entity> = table.threat.keys()
+            .filter(|source| source_query.get(**source).is_err())
+            .copied()
+            .collect();
+        for source in gone {
+            table.remove(source);
+        }
+
+        table.decay(delta_seconds);
+        table.choose(now);
+    }
+}
+
+/// Keeps the targeted point on top of the targeted entity while it's perceived
+pub fn track_targets(
+    mut target_query: Query<&mut Target>,
+    transform_query: Query<&Transform>,
+) {
+    for mut target in target_query.iter_mut() {
+        let (Some(entity), true) = (target.entity, target.has_target()) else {
+            continue;
+        };
+
+        let Ok(transform) = transform_query.get(entity) else {
+            target.remove_target();
+            continue;
+        };
+
+        target.point = Some(transform.translation);
+        target.last_known = Some(transform.translation);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_only_switches_past_the_margin() {
+        let (first, second) = (Entity::from_raw(1), Entity::from_raw(2));
+        let mut table = ThreatTable::new(100.0);
+
+        table.add(first, 10.0);
+        assert_eq!(table.choose(0.0), Some(first));
+
+        table.add(second, 11.0);
+        assert_eq!(table.choose(2.0), Some(first));
+
+        table.add(second, 2.0);
+        assert_eq!(table.choose(2.0), Some(second));
+    }
+}
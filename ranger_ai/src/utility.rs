@@ -0,0 +1,272 @@
+use bevy::prelude::*;
+
+use crate::{blackboard::keys, Blackboard, Target};
+
+/// Maps a normalized input (0 to 1) onto a score (0 to 1)
+#[derive(Debug, Clone, Copy)]
+pub enum ResponseCurve {
+    Linear { slope: f32, intercept: f32 },
+    /// slope * x^exponent + intercept
+    Polynomial { exponent: f32, slope: f32, intercept: f32 },
+    /// An S-curve, steepness around 10 makes for a fairly sharp one
+    Logistic { steepness: f32, midpoint: f32 },
+    /// 1 at and above the threshold, 0 below
+    Step { threshold: f32 },
+}
+
+impl ResponseCurve {
+    pub const IDENTITY: Self = ResponseCurve::Linear { slope: 1.0, intercept: 0.0 };
+    pub const INVERSE: Self = ResponseCurve::Linear { slope: -1.0, intercept: 1.0 };
+
+    pub fn evaluate(&self, x: f32) -> f32 {
+        let x = x.clamp(0.0, 1.0);
+
+        let y = match *self {
+            ResponseCurve::Linear { slope, intercept } => slope * x + intercept,
+            ResponseCurve::Polynomial { exponent, slope, intercept } => slope * x.powf(exponent) + intercept,
+            ResponseCurve::Logistic { steepness, midpoint } => 1.0 / (1.0 + (-steepness * (x - midpoint)).exp()),
+            ResponseCurve::Step { threshold } => if x >= threshold { 1.0 } else { 0.0 },
+        };
+
+        y.clamp(0.0, 1.0)
+    }
+}
+
+pub struct UtilityContext<'a> {
+    pub position: Vec3,
+    pub target: Option<&'a Target>,
+    pub blackboard: &'a Blackboard,
+}
+
+/// One input to a decision. Scores are expected to be between 0 and 1.
+pub trait Consideration: Send + Sync {
+    fn name(&self) -> &str;
+    fn score(&self, context: &UtilityContext) -> f32;
+}
+
+/// Reads a float off the blackboard and maps `range` onto the curve.
+/// A missing key scores 0, which vetoes the action.
+pub struct BlackboardConsideration {
+    pub key: String,
+    pub range: (f32, f32),
+    pub curve: ResponseCurve,
+}
+
+impl BlackboardConsideration {
+    pub fn new(key: &str, range: (f32, f32), curve: ResponseCurve) -> Self {
+        Self { key: key.to_string(), range, curve }
+    }
+}
+
+impl Consideration for BlackboardConsideration {
+    fn name(&self) -> &str {
+        &self.key
+    }
+
+    fn score(&self, context: &UtilityContext) -> f32 {
+        let Some(value) = context.blackboard.float(&self.key) else {
+            return 0.0;
+        };
+
+        let (min, max) = self.range;
+        self.curve.evaluate((value - min) / (max - min))
+    }
+}
+
+/// Distance to the target, out to `max_distance`. Scores 0 without a target.
+pub struct TargetDistance {
+    pub max_distance: f32,
+    pub curve: ResponseCurve,
+}
+
+impl Consideration for TargetDistance {
+    fn name(&self) -> &str {
+        "target distance"
+    }
+
+    fn score(&self, context: &UtilityContext) -> f32 {
+        let Some(point) = context.target.and_then(|target| target.point) else {
+            return 0.0;
+        };
+
+        self.curve.evaluate(context.position.distance(point) / self.max_distance)
+    }
+}
+
+/// Health over max health, both read from the blackboard
+pub struct HealthFraction {
+    pub curve: ResponseCurve,
+}
+
+impl Consideration for HealthFraction {
+    fn name(&self) -> &str {
+        "health"
+    }
+
+    fn score(&self, context: &UtilityContext) -> f32 {
+        let (Some(health), Some(max_health)) = (
+            context.blackboard.float(keys::HEALTH),
+            context.blackboard.float(keys::MAX_HEALTH),
+        ) else {
+            return 0.0;
+        };
+
+        self.curve.evaluate(health / max_health)
+    }
+}
+
+pub struct UtilityAction {
+    pub name: String,
+    pub weight: f32,
+    considerations: Vec<Box<dyn Consideration>>,
+}
+
+impl UtilityAction {
+    pub fn new(name: &str) -> Self {
+        Self { name: name.to_string(), weight: 1.0, considerations: vec![] }
+    }
+
+    pub fn weight(mut self, weight: f32) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    pub fn consider(mut self, consideration: impl Consideration + 'static) -> Self {
+        self.considerations.push(Box::new(consideration));
+        self
+    }
+
+    /// Multiplies the considerations together. Since that punishes actions for simply having more
+    /// considerations, each score gets made up for a bit depending on how many there are.
+    /// http://www.gdcvault.com/play/1021848/Building-a-Better-Centurion-AI
+    fn score(&self, context: &UtilityContext) -> ScoredAction {
+        let modification = 1.0 - 1.0 / self.considerations.len().max(1) as f32;
+        let mut total = self.weight;
+        let mut considerations = vec![];
+
+        for consideration in self.considerations.iter() {
+            let score = consideration.score(context);
+            let make_up = (1.0 - score) * modification;
+
+            total *= score + make_up * score;
+            considerations.push((consideration.name().to_string(), score));
+        }
+
+        ScoredAction { action: self.name.clone(), total, considerations }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ScoredAction {
+    pub action: String,
+    pub total: f32,
+    pub considerations: Vec<(String, f32)>,
+}
+
+/// Picks whichever action scores highest, every update.
+/// The scores of the last decision are kept around for debugging.
+#[derive(Component)]
+pub struct UtilityAi {
+    actions: Vec<UtilityAction>,
+    pub chosen: Option<String>,
+    pub scores: Vec<ScoredAction>,
+}
+
+impl UtilityAi {
+    pub fn new() -> Self {
+        Self { actions: vec![], chosen: None, scores: vec![] }
+    }
+
+    pub fn action(mut self, action: UtilityAction) -> Self {
+        self.actions.push(action);
+        self
+    }
+
+    pub fn is_chosen(&self, action: &str) -> bool {
+        self.chosen.as_deref() == Some(action)
+    }
+
+    fn decide(&mut self, context: &UtilityContext) -> Option<&str> {
+        self.scores = self.actions.iter().map(|action| action.score(context)).collect();
+
+        self.chosen = self.scores.iter()
+            .filter(|scored| scored.total > 0.0)
+            .max_by(|a, b| a.total.total_cmp(&b.total))
+            .map(|scored| scored.action.clone());
+
+        self.chosen.as_deref()
+    }
+}
+
+impl Default for UtilityAi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct ActionChosen {
+    pub entity: Entity,
+    pub action: Option<String>,
+}
+
+/// Logs every decision with the scores that led to it at debug level when enabled. Off by default.
+#[derive(Resource, Default)]
+pub struct UtilityDebug(pub bool);
+
+#[allow(clippy::type_complexity)]
+pub fn score_utility_ai(
+    mut ai_query: Query<(Entity, &mut UtilityAi, &Transform, Option<&Target>, Option<&Blackboard>), Without<crate::Stunned>>,
+    mut action_chosen_events: EventWriter<ActionChosen>,
+    res_utility_debug: Res<UtilityDebug>,
+) {
+    let empty_blackboard = Blackboard::default();
+
+    for (entity, mut utility_ai, transform, target, blackboard) in ai_query.iter_mut() {
+        let context = UtilityContext {
+            position: transform.translation,
+            target,
+            blackboard: blackboard.unwrap_or(&empty_blackboard),
+        };
+
+        let previous = utility_ai.chosen.clone();
+        let chosen = utility_ai.decide(&context).map(str::to_string);
+
+        if chosen == previous {
+            continue;
+        }
+
+        if res_utility_debug.0 {
+            debug!("{:?} chose {:?}", entity, chosen);
+            for scored in utility_ai.scores.iter() {
+                debug!("  {}: {:.3} {:?}", scored.action, scored.total, scored.considerations);
+            }
+        }
+
+        action_chosen_events.send(ActionChosen { entity, action: chosen });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn low_health_makes_retreating_win() {
+        let mut utility_ai = UtilityAi::new()
+            .action(UtilityAction::new("chase")
+                .consider(HealthFraction { curve: ResponseCurve::IDENTITY }))
+            .action(UtilityAction::new("retreat")
+                .consider(HealthFraction { curve: ResponseCurve::INVERSE }));
+        let mut blackboard = Blackboard::new();
+        blackboard.set_float(keys::MAX_HEALTH, 50.0);
+
+        blackboard.set_float(keys::HEALTH, 40.0);
+        let context = UtilityContext { position: Vec3::ZERO, target: None, blackboard: &blackboard };
+        assert_eq!(utility_ai.decide(&context), Some("chase"));
+
+        blackboard.set_float(keys::HEALTH, 10.0);
+        let context = UtilityContext { position: Vec3::ZERO, target: None, blackboard: &blackboard };
+        assert_eq!(utility_ai.decide(&context), Some("retreat"));
+    }
+}
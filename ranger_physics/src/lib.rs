@@ -157,7 +157,7 @@ impl AABB {
         let t_max_y = (self_sides.top - origin.y) / direction.y;
         
         // with this we get the maximum t_min and the minimum t_max
-        let t_min = (t_min_x.min(t_max_x)).max(t_min_x.min(t_max_x));
+        let t_min = (t_min_x.min(t_max_x)).max(t_min_y.min(t_max_y));
         let t_max = (t_min_x.max(t_max_x)).min(t_min_y.max(t_max_y));
         
         // run some checks on the values
@@ -176,6 +176,22 @@ impl AABB {
         return Some(t_min);
     }
 
+    /// The outward normal of whichever side `point` is closest to, for bouncing things off
+    pub fn normal_at(&self, point: Vec3) -> Vec3 {
+        let sides = self.sides();
+
+        [
+            ((point.x - sides.left).abs(), Vec3::NEG_X),
+            ((sides.right - point.x).abs(), Vec3::X),
+            ((point.y - sides.bottom).abs(), Vec3::NEG_Y),
+            ((sides.top - point.y).abs(), Vec3::Y),
+        ]
+            .into_iter()
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, normal)| normal)
+            .unwrap_or(Vec3::ZERO)
+    }
+
     /// Here for moving/static and static/static, given a point; it returns a point which is
     /// derived from the minimum distance required to get out of the bounding box.
     ///
@@ -222,43 +238,69 @@ impl AABB {
         Some(minkowski.get_bounds_point_from_minimum_distance(self.point))
     }
 
-    fn dynamic_static(&self, my_path: &Path, other: &AABB) -> bool {
-        // minkowski is created at the other box's position. we then raycast our movement vector
-        // and if we get something back from the raycast, we'll collide.
-        let minkowski = self.minkowski(other);
-        if let Some(_) = minkowski.raycast(self.point, my_path.movement) {
+    /// Whether the two touch right now, or are going to within the next `delta` seconds going by
+    /// how they're moving
+    pub fn is_colliding(first_aabb: &AABB, first_path: &Path, second_aabb: &AABB, second_path: &Path, delta: f32) -> bool {
+        if first_aabb.static_static(second_aabb).is_some() {
             return true;
         }
 
-        false
-    }
-
-    pub fn is_colliding(first_aabb: &AABB, first_path: &Path, second_aabb: &AABB, second_path: &Path) -> bool {
-        match (first_path.is_moving(), second_path.is_moving()) {
-            (true, false) => {
-                return first_aabb.dynamic_static(first_path, second_aabb);
-            },
-            (false, true) => {
-                return second_aabb.dynamic_static(first_path, first_aabb);
-            },
-            (true, true) => {
-                // probably won't bother doing this, ever.
-                return false;
-            },
-            (false, false) => {
-                if let Some(_) = first_aabb.static_static(second_aabb) {
-                    return true;
-                }
-            },
+        // as far as the first one's concerned, the second one stands still
+        let movement = first_path.movement - second_path.movement;
+        if movement == Vec3::ZERO {
+            return false;
         }
 
-        false
+        // the minkowski is created at the other box's position. we then raycast our movement
+        // and if we get something back that's close enough, we'll collide.
+        first_aabb.minkowski(second_aabb)
+            .raycast(first_aabb.point, movement)
+            .is_some_and(|distance| distance <= movement.length() * delta)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn it_works() {
     }
+
+    #[test]
+    fn collides_within_the_update() {
+        let size = Vec2::splat(10.0);
+        let first = AABB::new(Vec3::ZERO, size);
+        let second = AABB::new(Vec3::new(30.0, 0.0, 0.0), size);
+        let mut towards = Path::new(100.0);
+        towards.movement = Vec3::new(100.0, 0.0, 0.0);
+        let standing = Path::new(100.0);
+
+        assert!(AABB::is_colliding(&first, &towards, &second, &standing, 0.5));
+        assert!(!AABB::is_colliding(&first, &towards, &second, &standing, 0.1));
+        assert!(!AABB::is_colliding(&first, &towards, &second, &towards, 0.5));
+        assert!(AABB::is_colliding(&first, &standing, &AABB::new(Vec3::new(5.0, 0.0, 0.0), size), &standing, 0.0));
+    }
+
+    #[test]
+    fn raycasts_enter_through_the_later_side() {
+        // hits the bottom side, well after passing the line of the left side
+        let aabb = AABB::new(Vec3::ZERO, Vec2::splat(10.0));
+        let origin = Vec3::new(-10.0, -20.0, 0.0);
+        let direction = Vec3::new(1.0, 2.0, 0.0);
+
+        let distance = aabb.raycast(origin, direction).unwrap();
+        let hit = origin + direction.normalize() * distance;
+        assert!((hit.y + 5.0).abs() < 0.001);
+        assert!((hit.x + 2.5).abs() < 0.001);
+    }
+
+    #[test]
+    fn normals_point_out_of_the_closest_side() {
+        let wall = AABB::new(Vec3::ZERO, Vec2::new(100.0, 20.0));
+
+        assert_eq!(wall.normal_at(Vec3::new(-50.0, 2.0, 0.0)), Vec3::NEG_X);
+        assert_eq!(wall.normal_at(Vec3::new(30.0, 10.0, 0.0)), Vec3::Y);
+        assert_eq!(wall.normal_at(Vec3::new(30.0, -9.0, 0.0)), Vec3::NEG_Y);
+    }
 }
@@ -0,0 +1,328 @@
+use bevy::asset::LoadedFolder;
+use bevy::ecs::system::{EntityCommands, SystemParam};
+use bevy::prelude::*;
+use serde::Deserialize;
+use ranger_physics::{AABB, Path};
+use ranger_ai::{Avoidance, BehaviorTree, Blackboard, NavPath, PatrolRoute, Perception, Target, ThreatTable};
+
+/// Which AI an archetype runs on
+#[derive(Debug, Clone, Deserialize)]
+pub enum Behaviour {
+    /// Runs at the target and rams it, see `basic_enemy`
+    Melee,
+    /// Keeps its distance and shoots, see `ranged_enemy`
+    Ranged,
+    /// Path to a behavior tree asset
+    Tree(String),
+}
+
+#[derive(Component, Debug, Clone, Copy, Deserialize)]
+pub struct EnemyWeapon {
+    pub damage: f32,
+    /// Seconds between shots
+    pub fire_interval: f32,
+    pub bullet_speed: f32,
+    #[serde(default)]
+    pub ballistics: super::bullet::Ballistics,
+    /// Put on whatever the bullets hit
+    #[serde(default)]
+    pub effect: Option<super::status::StatusEffect>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Drop {
+    pub item: String,
+    /// From 0 to 1
+    pub chance: f32,
+}
+
+/// An enemy type, loaded from `assets/enemies/*.enemy.ron`
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct EnemyArchetype {
+    pub name: String,
+    pub size: (f32, f32),
+    pub health: f32,
+    pub speed: f32,
+    pub detect_range: f32,
+    pub sprite: String,
+    #[serde(default)]
+    pub tint: Option<(f32, f32, f32)>,
+    pub behaviour: Behaviour,
+    /// Dealt to the player on touch
+    #[serde(default)]
+    pub contact_damage: f32,
+    #[serde(default)]
+    pub resistances: super::damage::Resistances,
+    #[serde(default)]
+    pub shield: Option<super::damage::ShieldStats>,
+    #[serde(default)]
+    pub weapon: Option<EnemyWeapon>,
+    #[serde(default)]
+    pub drops: Vec<Drop>,
+    #[serde(default)]
+    pub score: u32,
+}
+
+impl EnemyArchetype {
+    pub(super) fn size(&self) -> Vec2 {
+        Vec2::new(self.size.0, self.size.1)
+    }
+
+    fn sprite(&self) -> Sprite {
+        Sprite {
+            color: self.tint.map_or(Color::WHITE, |(r, g, b)| Color::rgb(r, g, b)),
+            custom_size: Some(self.size()),
+            ..default()
+        }
+    }
+}
+
+/// Which archetype an enemy was spawned from, so it can be updated when the file changes
+#[derive(Component)]
+pub struct Archetype {
+    pub id: AssetId<EnemyArchetype>,
+    pub health_scale: f32,
+}
+
+/// Keeps every archetype in the enemies folder loaded
+#[derive(Resource)]
+struct ArchetypeFolder(#[allow(dead_code)] Handle<LoadedFolder>);
+
+#[derive(Resource, Default, Debug)]
+pub struct Score(pub u32);
+
+/// Everything needed to spawn enemies by archetype name
+#[derive(SystemParam)]
+pub struct EnemySpawner<'w, 's> {
+    commands: Commands<'w, 's>,
+    asset_server: Res<'w, AssetServer>,
+    archetypes: Res<'w, Assets<EnemyArchetype>>,
+}
+
+impl EnemySpawner<'_, '_> {
+    /// Spawns the archetype called `name` at `position`, with its health multiplied by
+    /// `health_scale`. Returns None if there's no such archetype, or it hasn't loaded yet.
+    pub fn spawn(
+        &mut self,
+        name: &str,
+        position: Vec3,
+        route: Option<PatrolRoute>,
+        health_scale: f32,
+    ) -> Option<EntityCommands<'_>> {
+        let (id, archetype) = self.archetypes.iter().find(|(_, archetype)| archetype.name == name)?;
+
+        let mut enemy = self.commands.spawn((
+            Archetype { id, health_scale },
+            AABB::new(position, archetype.size()),
+            super::Health::new(archetype.health * health_scale),
+            archetype.resistances,
+            super::Faction::Enemy,
+            Path::new(archetype.speed),
+            super::BaseSpeed(archetype.speed),
+            NavPath::default(),
+            Avoidance::default(),
+            Target::new(None),
+            ThreatTable::new(archetype.detect_range),
+            Perception::new(archetype.detect_range, std::f32::consts::TAU),
+            Blackboard::new(),
+            SpriteBundle {
+                sprite: archetype.sprite(),
+                texture: self.asset_server.load(&archetype.sprite),
+                transform: Transform::from_translation(position),
+                ..default()
+            },
+        ));
+
+        match &archetype.behaviour {
+            Behaviour::Melee => { enemy.insert(super::basic_enemy::behaviour(archetype)); },
+            Behaviour::Ranged => { enemy.insert(super::ranged_enemy::behaviour()); },
+            Behaviour::Tree(tree) => { enemy.insert(BehaviorTree::new(self.asset_server.load(tree))); },
+        }
+
+        if let Some(weapon) = archetype.weapon {
+            enemy.insert(weapon);
+        }
+
+        if let Some(shield) = archetype.shield {
+            enemy.insert(super::damage::Shield::new(shield));
+        }
+
+        if archetype.contact_damage > 0.0 {
+            enemy.insert(super::damage::ContactDamage(archetype.contact_damage));
+        }
+
+        if let Some(route) = route {
+            enemy.insert(route);
+        }
+
+        Some(enemy)
+    }
+}
+
+fn load_archetypes(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    commands.insert_resource(ArchetypeFolder(asset_server.load_folder("enemies")));
+}
+
+/// Pushes changes to an archetype file onto every enemy already spawned from it, including
+/// gaining or losing a shield or contact damage. The behaviour itself only changes for enemies
+/// spawned after the change.
+#[allow(clippy::type_complexity)]
+pub fn apply_archetype_changes(
+    mut archetype_events: EventReader<AssetEvent<EnemyArchetype>>,
+    mut enemy_query: Query<(
+        Entity,
+        &Archetype,
+        &mut AABB,
+        &mut Sprite,
+        &mut Handle<Image>,
+        &mut super::Health,
+        &mut super::damage::Resistances,
+        Option<&mut super::damage::Shield>,
+        &mut Perception,
+        &mut ThreatTable,
+        Option<&mut Path>,
+        Option<&mut super::BaseSpeed>,
+        Option<&mut EnemyWeapon>,
+        Option<&mut super::damage::ContactDamage>,
+    )>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    res_archetypes: Res<Assets<EnemyArchetype>>,
+) {
+    for event in archetype_events.read() {
+        let AssetEvent::Modified { id } = event else {
+            continue;
+        };
+
+        let Some(archetype) = res_archetypes.get(*id) else {
+            continue;
+        };
+
+        for (
+            entity,
+            enemy_archetype,
+            mut aabb,
+            mut sprite,
+            mut texture,
+            mut health,
+            mut resistances,
+            shield,
+            mut perception,
+            mut threat_table,
+            path,
+            base_speed,
+            weapon,
+            contact_damage,
+        ) in enemy_query.iter_mut() {
+            if enemy_archetype.id != *id {
+                continue;
+            }
+
+            *aabb = AABB::new(aabb.point, archetype.size());
+            *sprite = archetype.sprite();
+            *texture = asset_server.load(&archetype.sprite);
+            health.set_max(archetype.health * enemy_archetype.health_scale);
+            *resistances = archetype.resistances;
+            perception.range = archetype.detect_range;
+            threat_table.proximity_range = archetype.detect_range;
+
+            if let Some(mut path) = path {
+                path.velocity = archetype.speed;
+            }
+
+            if let Some(mut base_speed) = base_speed {
+                base_speed.0 = archetype.speed;
+            }
+
+            if let (Some(mut weapon), Some(new_weapon)) = (weapon, archetype.weapon) {
+                *weapon = new_weapon;
+            }
+
+            match (shield, archetype.shield) {
+                (Some(mut shield), Some(stats)) => shield.set_stats(stats),
+                (None, Some(stats)) => { commands.entity(entity).insert(super::damage::Shield::new(stats)); },
+                (Some(_), None) => { commands.entity(entity).remove::<super::damage::Shield>(); },
+                (None, None) => (),
+            }
+
+            match (contact_damage, archetype.contact_damage > 0.0) {
+                (Some(mut contact_damage), true) => contact_damage.0 = archetype.contact_damage,
+                (None, true) => { commands.entity(entity).insert(super::damage::ContactDamage(archetype.contact_damage)); },
+                (Some(_), false) => { commands.entity(entity).remove::<super::damage::ContactDamage>(); },
+                (None, false) => (),
+            }
+        }
+    }
+}
+
+/// Rolls the drops of enemies that just died, and hands out the score if the player did it
+fn reward_kills(
+    mut death_events: EventReader<super::damage::DeathEvent>,
+    enemy_query: Query<(&Archetype, &Transform)>,
+    player_query: Query<(), With<super::player::Player>>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    res_archetypes: Res<Assets<EnemyArchetype>>,
+    mut res_score: ResMut<Score>,
+) {
+    for death in death_events.read() {
+        let Ok((archetype, transform)) = enemy_query.get(death.entity) else {
+            continue;
+        };
+
+        let Some(archetype) = res_archetypes.get(archetype.id) else {
+            continue;
+        };
+
+        if player_query.contains(death.killer) {
+            res_score.0 += archetype.score;
+        }
+
+        for drop in archetype.drops.iter() {
+            if fastrand::f32() < drop.chance {
+                super::pickup::spawn_pickup(&mut commands, &asset_server, &drop.item, transform.translation);
+            }
+        }
+    }
+}
+
+pub struct ArchetypePlugin;
+
+impl Plugin for ArchetypePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_asset::<EnemyArchetype>()
+            .register_asset_loader(ranger_ai::RonLoader::<EnemyArchetype>::new(&["enemy.ron"]))
+            .init_resource::<Score>()
+            .add_systems(Startup, load_archetypes)
+            .add_systems(Update, (
+                apply_archetype_changes,
+                reward_kills
+                    .after(super::damage::apply_damage)
+                    .before(super::damage::despawn_dead),
+            ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn archetypes_parse() {
+        for file in [
+            include_str!("../../assets/enemies/basic.enemy.ron"),
+            include_str!("../../assets/enemies/ranged.enemy.ron"),
+            include_str!("../../assets/enemies/elite.enemy.ron"),
+        ] {
+            let archetype: EnemyArchetype = ron::from_str(file).unwrap();
+            assert!(archetype.health > 0.0);
+        }
+
+        let tree: Result<ranger_ai::BehaviorTreeAsset, _> = ron::from_str(include_str!("../../assets/ai/elite.bt.ron"));
+        assert!(tree.is_ok());
+    }
+}
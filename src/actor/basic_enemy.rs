@@ -1,115 +1,281 @@
 use bevy::prelude::*;
 use ranger_physics::{AABB, Path};
-use ranger_ai::Target;
-
-#[derive(Resource)]
-pub struct EnemySpawnTimer(pub Timer);
+use ranger_ai::{blackboard::keys, AiState, Blackboard, InfluenceLayer, InfluenceMap, NavPath, PatrolRoute, SquadAgent, SquadMember, StateContext, StateMachine, Target};
+use ranger_ai::utility::{BlackboardConsideration, HealthFraction, ResponseCurve, TargetDistance, UtilityAction, UtilityAi};
+use crate::world::navigation::{self, NavMeshes};
+use super::archetype::EnemyArchetype;
 
 #[derive(Component)]
 pub struct BasicEnemy;
 
+// basic enemies that are free to act, i.e. not stunned
+type Acting = (With<BasicEnemy>, Without<ranger_ai::Stunned>);
 
-const BASIC_ENEMY_SIZE: Vec2 = Vec2::new(50.0, 50.0);
+// close enough to ram the target
+const BASIC_ENEMY_ATTACK_RANGE: f32 = 60.0;
+// other enemies closer than this count as backup
+const BASIC_ENEMY_ALLY_RANGE: f32 = 200.0;
+// how far a fleeing enemy looks for somewhere safer
+const BASIC_ENEMY_FLEE_RANGE: f32 = 225.0;
+const RETREATING: &str = "retreating";
 
-fn spawn(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut res_enemy_spawn_timer: ResMut<EnemySpawnTimer>,
-    res_time: Res<Time>,
-) {
-    if !res_enemy_spawn_timer.0.tick(res_time.delta()).just_finished() {
-        return;
-    }
+pub(super) fn is_dead(context: &StateContext) -> bool {
+    context.blackboard.float(keys::HEALTH).is_some_and(|health| health <= 0.0)
+}
+
+pub(super) fn sees_target(context: &StateContext) -> bool {
+    context.target.has_target()
+}
+
+pub(super) fn lost_target(context: &StateContext) -> bool {
+    context.target.is_searching(context.now)
+}
+
+pub(super) fn gave_up(context: &StateContext) -> bool {
+    !context.target.has_target() && !context.target.is_searching(context.now)
+}
+
+fn in_attack_range(context: &StateContext) -> bool {
+    context.blackboard.float(keys::DISTANCE_TO_TARGET)
+        .is_some_and(|distance| distance <= BASIC_ENEMY_ATTACK_RANGE)
+}
+
+fn out_of_attack_range(context: &StateContext) -> bool {
+    sees_target(context) && !in_attack_range(context)
+}
 
-    commands.spawn((
+fn has_route(context: &StateContext) -> bool {
+    context.blackboard.bool(keys::HAS_PATROL_ROUTE).unwrap_or(false)
+}
+
+fn back_to_patrol(context: &StateContext) -> bool {
+    gave_up(context) && has_route(context)
+}
+
+fn wants_to_retreat(context: &StateContext) -> bool {
+    sees_target(context) && context.blackboard.bool(RETREATING).unwrap_or(false)
+}
+
+fn wants_to_fight(context: &StateContext) -> bool {
+    sees_target(context) && !context.blackboard.bool(RETREATING).unwrap_or(false)
+}
+
+fn state_machine() -> StateMachine {
+    StateMachine::new(AiState::Idle)
+        .any_transition(AiState::Dead, is_dead)
+        .transition(AiState::Idle, AiState::Chase, sees_target)
+        .transition(AiState::Patrol, AiState::Chase, sees_target)
+        .transition(AiState::Idle, AiState::Patrol, has_route)
+        .transition(AiState::Patrol, AiState::Idle, |context| !has_route(context))
+        .transition(AiState::Search, AiState::Patrol, back_to_patrol)
+        .transition(AiState::Search, AiState::Chase, sees_target)
+        .transition(AiState::Chase, AiState::Flee, wants_to_retreat)
+        .transition(AiState::Attack, AiState::Flee, wants_to_retreat)
+        .transition(AiState::Flee, AiState::Chase, wants_to_fight)
+        .transition(AiState::Flee, AiState::Search, lost_target)
+        .transition(AiState::Flee, AiState::Idle, gave_up)
+        .transition(AiState::Chase, AiState::Attack, in_attack_range)
+        .transition(AiState::Attack, AiState::Chase, out_of_attack_range)
+        .transition(AiState::Chase, AiState::Search, lost_target)
+        .transition(AiState::Attack, AiState::Search, lost_target)
+        .transition(AiState::Chase, AiState::Idle, gave_up)
+        .transition(AiState::Attack, AiState::Idle, gave_up)
+        .transition(AiState::Search, AiState::Idle, gave_up)
+        // dead enemies shouldn't be pushed around or collided with until they're despawned
+        .on_enter(AiState::Dead, |entity| { entity.remove::<Path>(); })
+}
+
+/// Hurt enemies without backup back off, the rest keep pushing
+fn utility_ai(detect_range: f32) -> UtilityAi {
+    UtilityAi::new()
+        .action(UtilityAction::new("chase")
+            .consider(TargetDistance {
+                max_distance: detect_range,
+                curve: ResponseCurve::Linear { slope: -0.5, intercept: 1.0 },
+            })
+            .consider(HealthFraction { curve: ResponseCurve::Logistic { steepness: 10.0, midpoint: 0.3 } }))
+        .action(UtilityAction::new("retreat")
+            .weight(0.9)
+            .consider(HealthFraction { curve: ResponseCurve::INVERSE })
+            .consider(BlackboardConsideration::new(
+                keys::ALLY_COUNT,
+                (0.0, 4.0),
+                ResponseCurve::Polynomial { exponent: 2.0, slope: -1.0, intercept: 1.0 },
+            )))
+}
+
+/// What makes an enemy with `Behaviour::Melee` a basic enemy
+pub(super) fn behaviour(archetype: &EnemyArchetype) -> impl Bundle {
+    (
         BasicEnemy,
-        AABB::new(Vec3::ZERO, BASIC_ENEMY_SIZE),
-        crate::actor::Health(50.0),
-        Path::new(0.0),
-        Target::new(None),
-        SpriteBundle {
-            sprite: Sprite {
-                custom_size: Some(BASIC_ENEMY_SIZE),
-                ..default()
-            },
-            texture: asset_server.load("sprites/enemy_placeholder.png"),
-            ..default()
-        },
-    ));
+        SquadAgent,
+        state_machine(),
+        utility_ai(archetype.detect_range),
+    )
 }
 
-fn detect_player(
-    player_query: Query<&Transform, With<super::player::Player>>,
-    mut enemy_query: Query<(&mut Target, &Transform), With<BasicEnemy>>,
+/// Copies what the state machine and utility AI need to know about the enemy onto its blackboard
+#[allow(clippy::type_complexity)]
+fn update_blackboard(
+    mut enemy_query: Query<(&mut Blackboard, &super::Health, &Target, &Transform, &UtilityAi, Option<&PatrolRoute>), With<BasicEnemy>>,
+    ally_query: Query<&Transform, With<BasicEnemy>>,
 ) {
-    if player_query.get_single().is_err() {
-        return;
-    }
+    for (mut blackboard, health, target, transform, utility_ai, route) in enemy_query.iter_mut() {
+        blackboard.set_float(keys::HEALTH, health.current);
+        blackboard.set_float(keys::MAX_HEALTH, health.max);
+        blackboard.set_bool(RETREATING, utility_ai.is_chosen("retreat"));
+        blackboard.set_bool(keys::HAS_PATROL_ROUTE, route.is_some_and(|route| !route.is_finished()));
 
-    let player_transform = player_query.single();
+        // counts the enemy itself as well, which doesn't matter since everyone does it
+        let allies = ally_query.iter()
+            .filter(|ally| ally.translation.distance(transform.translation) <= BASIC_ENEMY_ALLY_RANGE)
+            .count();
+        blackboard.set_float(keys::ALLY_COUNT, allies as f32 - 1.0);
 
-    for (mut enemies_target, enemy_transform) in enemy_query.iter_mut() {
-        if player_transform.translation.distance(enemy_transform.translation) > 300.0 {
-            enemies_target.remove_target();
-            continue;
+        match target.point {
+            Some(point) => blackboard.set_float(keys::DISTANCE_TO_TARGET, transform.translation.distance(point)),
+            None => blackboard.remove(keys::DISTANCE_TO_TARGET),
         }
-
-        enemies_target.set_target(player_transform.translation);
     }
 }
 
+fn is_pursuing(machine: &StateMachine) -> bool {
+    machine.is(AiState::Chase) || machine.is(AiState::Attack) || machine.is(AiState::Search)
+}
+
 fn focus_on_target(
-    mut enemy_query: Query<(&Target, &mut Transform), With<BasicEnemy>>,
+    mut enemy_query: Query<(&Target, &StateMachine, &mut Transform), Acting>,
+    res_time: Res<Time>,
 ) {
-    for (enemies_target, mut enemy_transform) in enemy_query.iter_mut() {
-        if !enemies_target.has_target() {
+    for (enemies_target, machine, mut enemy_transform) in enemy_query.iter_mut() {
+        if !is_pursuing(machine) {
             continue;
         }
 
+        let Some(destination) = enemies_target.destination(
+            enemy_transform.translation,
+            res_time.elapsed_seconds(),
+        ) else {
+            continue;
+        };
+
         let angle = crate::common::get_angle(
             enemy_transform.translation,
-            enemies_target.point.unwrap()
+            destination,
         );
 
         enemy_transform.rotation = Quat::from_rotation_z(angle);
     }
 }
 
+/// Goes after the target while it's in sight, and searches where it was last seen after losing it.
+/// Basic enemies attack by ramming, so attacking is just more pursuing.
+/// Enemies in a squad chase wherever their role tells them to.
+#[allow(clippy::type_complexity)]
 fn pursue_target(
-    mut enemy_query: Query<(&Target, &Transform, &mut Path), With<BasicEnemy>>,
+    mut enemy_query: Query<(&Target, &StateMachine, &Transform, &AABB, &mut Path, &mut NavPath, Option<&SquadMember>), Acting>,
+    mut res_nav_meshes: ResMut<NavMeshes>,
+    res_time: Res<Time>,
 ) {
-    for (enemies_target, transform, mut path) in enemy_query.iter_mut() {
-        if !enemies_target.has_target() {
+    for (enemies_target, machine, transform, aabb, mut path, mut nav_path, member) in enemy_query.iter_mut() {
+        if !is_pursuing(machine) {
             continue;
         }
 
-        path.steering(
-            &transform.translation,
-            &enemies_target.point.unwrap(),
+        let squad_goal = member
+            .filter(|_| machine.is(AiState::Chase))
+            .and_then(|member| member.goal);
+
+        let Some(destination) = squad_goal.or_else(|| enemies_target.destination(
+            transform.translation,
+            res_time.elapsed_seconds(),
+        )) else {
+            path.movement = Vec3::ZERO;
+            continue;
+        };
+
+        navigation::steer_towards(
+            &mut res_nav_meshes,
+            aabb,
+            &mut nav_path,
+            &mut path,
+            transform.translation,
+            destination,
         );
     }
 }
 
-fn hit_by_bullet(
-    mut enemy_query: Query<(Entity, &mut super::Health, &super::bullet::Hit), With<BasicEnemy>>,
-    mut commands: Commands,
+/// Walks the patrol route. After losing a target the enemy heads back to wherever it left off.
+fn patrol(
+    mut enemy_query: Query<(&StateMachine, &Transform, &AABB, &mut Path, &mut NavPath, &mut PatrolRoute), Acting>,
+    mut res_nav_meshes: ResMut<NavMeshes>,
+    res_time: Res<Time>,
 ) {
-    for (entity, mut health, _) in enemy_query.iter_mut() {
-        health.0 = 0.0;
-        commands.entity(entity).remove::<super::bullet::Hit>();
+    for (machine, transform, aabb, mut path, mut nav_path, mut route) in enemy_query.iter_mut() {
+        if !machine.is(AiState::Patrol) {
+            continue;
+        }
+
+        match route.update(transform.translation, res_time.elapsed_seconds()) {
+            Some(waypoint) => navigation::steer_towards(
+                &mut res_nav_meshes,
+                aabb,
+                &mut nav_path,
+                &mut path,
+                transform.translation,
+                waypoint,
+            ),
+            None => path.movement = Vec3::ZERO,
+        }
+    }
+}
+
+/// Runs for whatever nearby spot the player threatens the least, straight away from them if
+/// there's no influence map yet
+fn flee(
+    mut enemy_query: Query<(&Target, &StateMachine, &Transform, &AABB, &mut Path, &mut NavPath), Acting>,
+    mut res_nav_meshes: ResMut<NavMeshes>,
+    res_influence_map: Option<Res<InfluenceMap>>,
+) {
+    for (enemies_target, machine, transform, aabb, mut path, mut nav_path) in enemy_query.iter_mut() {
+        if !machine.is(AiState::Flee) {
+            continue;
+        }
+
+        let Some(point) = enemies_target.point else {
+            continue;
+        };
+
+        let safest = res_influence_map.as_ref().and_then(|influence_map| {
+            influence_map.lowest_near(InfluenceLayer::PlayerThreat, transform.translation, BASIC_ENEMY_FLEE_RANGE)
+        });
+
+        match safest {
+            Some(safest) => navigation::steer_towards(
+                &mut res_nav_meshes,
+                aabb,
+                &mut nav_path,
+                &mut path,
+                transform.translation,
+                safest,
+            ),
+            None => {
+                let away = transform.translation + (transform.translation - point);
+                path.steering(&transform.translation, &away);
+            },
+        }
     }
 }
 
-fn despawn(
-    enemy_query: Query<(Entity, &super::Health), With<BasicEnemy>>,
-    mut commands: Commands,
+fn idle(
+    mut enemy_query: Query<(&StateMachine, &mut Path), Acting>,
 ) {
-    for (entity, health) in enemy_query.iter() {
-        if health.0 > 0.0 {
+    for (machine, mut path) in enemy_query.iter_mut() {
+        if !machine.is(AiState::Idle) {
             continue;
         }
 
-        commands.entity(entity).despawn();
+        path.movement = Vec3::ZERO;
     }
 }
 
@@ -119,16 +285,25 @@ pub struct EnemyPlugin;
 impl Plugin for EnemyPlugin {
     fn build(&self, app: &mut App) {
         app
-            .insert_resource(EnemySpawnTimer(Timer::from_seconds(2.0, TimerMode::Once)))
             .add_systems(Update, (
-                spawn,
-                detect_player,
-                focus_on_target,
-                pursue_target,
-                hit_by_bullet,
-                despawn
-                    .after(super::bullet::check_for_collisions)
-                    .after(crate::world::set_field_coords),
+                update_blackboard
+                    .after(ranger_ai::squad::share_perception)
+                    .after(super::damage::apply_damage)
+                    .before(ranger_ai::state_machine::update_state_machines),
+                focus_on_target.after(ranger_ai::state_machine::update_state_machines),
+                pursue_target
+                    .after(ranger_ai::state_machine::update_state_machines)
+                    .after(ranger_ai::squad::assign_roles)
+                    .before(ranger_ai::avoidance::avoid_neighbours),
+                patrol
+                    .after(ranger_ai::state_machine::update_state_machines)
+                    .before(ranger_ai::avoidance::avoid_neighbours),
+                flee
+                    .after(ranger_ai::state_machine::update_state_machines)
+                    .before(ranger_ai::avoidance::avoid_neighbours),
+                idle
+                    .after(ranger_ai::state_machine::update_state_machines)
+                    .before(ranger_ai::avoidance::avoid_neighbours),
             ));
     }
 }
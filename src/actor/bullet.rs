@@ -1,91 +1,414 @@
+use bevy::ecs::system::{EntityCommands, SystemParam};
 use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use serde::Deserialize;
 use ranger_physics::{AABB, Path};
+use ranger_ai::targeting::lead_target;
+use crate::world::map::Grid;
+use super::damage::{DamageEvent, DamageKind};
+use super::explosion::{ExplosionEvent, ExplosionStats};
+use super::status::StatusEvent;
 
 #[derive(Component)]
-pub struct Bullet;
+pub struct Bullet {
+    pub owner: Entity,
+    pub faction: super::Faction,
+    pub damage: f32,
+    pub effect: Option<super::status::StatusEffect>,
+}
 
-#[derive(Component)]
-struct BulletDropoff(f32);
+pub const DEFAULT_BULLET_SPRITE: &str = "sprites/sussy.png";
+pub const DEFAULT_BULLET_SIZE: f32 = 10.0;
+// hidden bullets waiting to be fired, made up front
+const BULLET_POOL_SIZE: usize = 256;
+// anything slower than this has run out of steam
+const MIN_BULLET_SPEED: f32 = 50.0;
+// how far off the wall a ricochet puts the bullet, so it doesn't hit the same wall again
+const RICOCHET_CLEARANCE: f32 = 0.5;
 
-#[derive(Resource, Default)]
-// The cooldown needs to be dynamic, so no Timer
-struct ShootCooldown(f32);
+fn default_range() -> f32 {
+    1500.0
+}
 
-#[derive(Component)]
-pub struct Hit;
+fn default_end_speed() -> f32 {
+    0.5
+}
 
-fn spawn_bullets(
-    player_query: Query<&Transform, With<crate::actor::player::Player>>,
-    mut commands: Commands,
-    res_mouse_input: Res<ButtonInput<MouseButton>>,
-    res_cursor_coordinates: Res<crate::interface::CursorCoordinates>,
-    mut res_shoot_cooldown: ResMut<ShootCooldown>,
-    res_asset_server: Res<AssetServer>,
-    res_time: Res<Time>,
-) {
-    if res_shoot_cooldown.0 > 0.0 {
-        res_shoot_cooldown.0 -= res_time.delta_seconds();
-        return;
+fn default_lifetime() -> f32 {
+    3.0
+}
+
+/// How far and for how long a bullet flies, and how it slows down on the way
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct Ballistics {
+    /// World units, the bullet is gone once it's gone this far
+    #[serde(default = "default_range")]
+    pub range: f32,
+    /// Fraction of its speed the bullet has left at the end of its range. The speed drops off
+    /// linearly with the distance travelled, so 1 never slows down.
+    #[serde(default = "default_end_speed")]
+    pub end_speed: f32,
+    /// Seconds, for bullets going round in circles
+    #[serde(default = "default_lifetime")]
+    pub lifetime: f32,
+}
+
+impl Default for Ballistics {
+    fn default() -> Self {
+        Self { range: default_range(), end_speed: default_end_speed(), lifetime: default_lifetime() }
     }
+}
 
-    if !res_mouse_input.just_pressed(MouseButton::Left) {
-        return;
+impl Ballistics {
+    // how much of the speed is lost over the whole range
+    fn loss(&self) -> f32 {
+        (1.0 - self.end_speed).clamp(0.0, 1.0)
     }
 
-    if let Err(_) = player_query.get_single() {
-        return;
+    /// Distance travelled `time` seconds after being fired at `speed`. Solved rather than
+    /// stepped, so it comes out the same no matter how the time is split up into frames.
+    pub fn distance_at(&self, speed: f32, time: f32) -> f32 {
+        let loss = self.loss();
+        let distance = match loss > f32::EPSILON {
+            true => self.range / loss * (1.0 - (-speed * loss * time / self.range).exp()),
+            false => speed * time,
+        };
+
+        distance.min(self.range)
+    }
+
+    /// Speed after travelling `distance` when fired at `speed`
+    pub fn speed_at(&self, speed: f32, distance: f32) -> f32 {
+        speed * (1.0 - self.loss() * distance / self.range)
+    }
+}
+
+/// Where a bullet is in its flight
+#[derive(Component, Debug)]
+pub struct Flight {
+    pub ballistics: Ballistics,
+    /// What it was fired at
+    pub speed: f32,
+    pub travelled: f32,
+    pub age: f32,
+}
+
+impl Flight {
+    pub fn new(speed: f32, ballistics: Ballistics) -> Self {
+        Self { ballistics, speed, travelled: 0.0, age: 0.0 }
     }
 
-    commands.spawn((
-        Bullet,
-        BulletDropoff(0.0),
-        Path::r#static(
-            &player_query.single().translation,
-            &res_cursor_coordinates.0,
-            6000.0,
-        ),
-        SpriteBundle {
-            sprite: Sprite {
-                custom_size: Some(Vec2::splat(10.0)),
+    /// Moves the flight on by `delta` seconds, and returns how far the bullet gets in that time
+    pub fn advance(&mut self, delta: f32) -> f32 {
+        self.age += delta;
+        let travelled = self.ballistics.distance_at(self.speed, self.age);
+        let step = travelled - self.travelled;
+        self.travelled = travelled;
+
+        step
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.age >= self.ballistics.lifetime
+            || self.travelled >= self.ballistics.range
+            || self.ballistics.speed_at(self.speed, self.travelled) < MIN_BULLET_SPEED
+    }
+}
+
+/// How many more actors a bullet can go through, and which ones it already went through.
+/// Bullets without one stop at the first actor they hit.
+#[derive(Component, Debug, Default)]
+pub struct Pierce {
+    pub remaining: u32,
+    hit: Vec<Entity>,
+}
+
+impl Pierce {
+    pub fn new(remaining: u32) -> Self {
+        Self { remaining, hit: vec![] }
+    }
+}
+
+/// How many more times a bullet bounces off walls instead of stopping there
+#[derive(Component, Debug)]
+pub struct Ricochet(pub u32);
+
+/// Curves towards the closest actor of the other side in `range`
+#[derive(Component, Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct Homing {
+    /// Radians per second
+    pub turn_rate: f32,
+    pub range: f32,
+}
+
+/// Blows up wherever the bullet ends up hitting something
+#[derive(Component, Debug)]
+pub struct Explosive(pub ExplosionStats);
+
+/// Everything a weapon can do to its bullets, on top of the plain ones
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ProjectileModifiers {
+    pub pierce: u32,
+    pub ricochet: u32,
+    pub homing: Option<Homing>,
+    pub explosion: Option<ExplosionStats>,
+}
+
+impl ProjectileModifiers {
+    pub fn apply(&self, bullet: &mut EntityCommands) {
+        if self.pierce > 0 {
+            bullet.insert(Pierce::new(self.pierce));
+        }
+
+        if self.ricochet > 0 {
+            bullet.insert(Ricochet(self.ricochet));
+        }
+
+        if let Some(homing) = self.homing {
+            bullet.insert(homing);
+        }
+
+        if let Some(explosion) = self.explosion {
+            bullet.insert(Explosive(explosion));
+        }
+    }
+}
+
+/// Bullets that aren't flying right now. They keep their sprite around, hidden, and get
+/// everything else put back on when they're fired again.
+#[derive(Resource, Debug, Default)]
+pub struct BulletPool {
+    free: Vec<Entity>,
+    /// Put back this update. They only become free next update, once nothing's still going to
+    /// take their bullet parts off.
+    released: HashSet<Entity>,
+    textures: HashMap<String, Handle<Image>>,
+}
+
+fn fill_bullet_pool(
+    mut commands: Commands,
+    mut res_pool: ResMut<BulletPool>,
+) {
+    for _ in 0..BULLET_POOL_SIZE {
+        let entity = commands.spawn(SpriteBundle { visibility: Visibility::Hidden, ..default() }).id();
+        res_pool.free.push(entity);
+    }
+}
+
+/// Fires bullets out of the pool, and puts them back in once they're done
+#[derive(SystemParam)]
+pub struct Bullets<'w, 's> {
+    commands: Commands<'w, 's>,
+    pool: ResMut<'w, BulletPool>,
+    asset_server: Res<'w, AssetServer>,
+}
+
+impl Bullets<'_, '_> {
+    /// Fires a bullet from `origin` towards `destination`. Only makes a new entity when the
+    /// pool has run dry.
+    pub fn fire(
+        &mut self,
+        origin: Transform,
+        destination: Vec3,
+        flight: Flight,
+        bullet: Bullet,
+        sprite: &str,
+        size: f32,
+    ) -> EntityCommands<'_> {
+        let texture = match self.pool.textures.get(sprite) {
+            Some(texture) => texture.clone(),
+            None => {
+                let texture = self.asset_server.load(sprite.to_string());
+                self.pool.textures.insert(sprite.to_string(), texture.clone());
+                texture
+            },
+        };
+
+        let mut path = Path::new(flight.speed);
+        path.movement = (destination - origin.translation).normalize_or_zero() * flight.speed;
+
+        let entity = self.pool.free.pop()
+            .unwrap_or_else(|| self.commands.spawn(SpriteBundle::default()).id());
+
+        let mut bullet_commands = self.commands.entity(entity);
+        bullet_commands.insert((
+            bullet,
+            flight,
+            path,
+            Sprite {
+                custom_size: Some(Vec2::splat(size)),
                 ..default()
             },
-            texture: res_asset_server.load("sprites/sussy.png"),
-            transform: *player_query.single(),
-            ..default()
-        },
-    ));
+            texture,
+            origin,
+            Visibility::Inherited,
+        ));
+
+        bullet_commands
+    }
+
+    /// Takes everything that makes it a bullet off again and hides it, instead of despawning it
+    pub fn release(&mut self, entity: Entity) {
+        // a bullet can run out of range in the same update it hits something
+        if !self.pool.released.insert(entity) {
+            return;
+        }
+
+        self.commands.entity(entity)
+            .remove::<(Bullet, Flight, Pierce, Path, Ricochet, Homing, Explosive)>()
+            .insert(Visibility::Hidden);
+    }
+}
 
-    res_shoot_cooldown.0 = 0.1;
+fn recycle_bullets(
+    mut res_pool: ResMut<BulletPool>,
+) {
+    let BulletPool { free, released, .. } = &mut *res_pool;
+    free.extend(released.drain());
+}
+
+/// What comes out of a bullet hitting something
+#[derive(SystemParam)]
+pub struct Impacts<'w> {
+    damage_events: EventWriter<'w, DamageEvent>,
+    status_events: EventWriter<'w, StatusEvent>,
+    explosion_events: EventWriter<'w, ExplosionEvent>,
 }
 
+impl Impacts<'_> {
+    fn hit(&mut self, bullet: &Bullet, target: Entity) {
+        self.damage_events.send(DamageEvent {
+            target,
+            source: bullet.owner,
+            amount: bullet.damage,
+            kind: DamageKind::Kinetic,
+            periodic: false,
+        });
+
+        if let Some(effect) = bullet.effect {
+            self.status_events.send(StatusEvent { target, source: bullet.owner, effect });
+        }
+    }
+
+    fn explode(&mut self, bullet: &Bullet, explosive: Option<&Explosive>, point: Vec3) {
+        if let Some(explosive) = explosive {
+            self.explosion_events.send(ExplosionEvent { point, source: bullet.owner, stats: explosive.0 });
+        }
+    }
+}
+
+/// Only counts what the bullet is going to pass through this update, and never its own side.
+/// Walls stop bullets, unless they ricochet.
+#[allow(clippy::type_complexity)]
 pub fn check_for_collisions(
-    bullet_query: Query<(Entity, &Path, &mut Transform), With<Bullet>>,
-    actor_query: Query<(Entity, &AABB), Without<super::player::Player>>,
-    mut commands: Commands,
+    mut bullet_query: Query<(Entity, &Bullet, &mut Path, &mut Transform, Option<&mut Pierce>, Option<&mut Ricochet>, Option<&Explosive>)>,
+    actor_query: Query<(Entity, &AABB, &super::Faction)>,
+    grid_query: Query<&Grid>,
+    mut bullets: Bullets,
+    mut impacts: Impacts,
+    res_time: Res<Time>,
 ) {
-    for (b_entity, path, transform) in bullet_query.iter() {
-        // apparently delta timing it messes with the normalization, so i will refrain from that
-        let movement_vector = transform.translation + path.movement;
+    let walls: Vec<AABB> = grid_query.get_single()
+        .map(|grid| grid.solid_fields().map(|(point, size)| AABB::new(point, size)).collect())
+        .unwrap_or_default();
+
+    for (b_entity, bullet, mut path, mut transform, mut pierce, ricochet, explosive) in bullet_query.iter_mut() {
+        let origin = transform.translation;
+        let direction = path.movement.normalize_or_zero();
+        let travel = path.movement.length() * res_time.delta_seconds();
+
+        let wall = walls.iter()
+            .filter_map(|wall| wall.raycast(origin, path.movement)
+                .filter(|distance| *distance <= travel)
+                .map(|distance| (distance, wall)))
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+        // nothing behind the wall counts
+        let reach = wall.map_or(travel, |(distance, _)| distance);
+
+        let mut hits: Vec<(f32, Entity)> = actor_query.iter()
+            .filter(|(a_entity, _, faction)| **faction != bullet.faction && *a_entity != bullet.owner)
+            .filter(|(a_entity, _, _)| !pierce.as_ref().is_some_and(|pierce| pierce.hit.contains(a_entity)))
+            .filter_map(|(a_entity, aabb, _)| match aabb.point_collision(origin) {
+                true => Some((0.0, a_entity)),
+                false => aabb.raycast(origin, path.movement)
+                    .filter(|distance| *distance <= reach)
+                    .map(|distance| (distance, a_entity)),
+            })
+            .collect();
+        hits.sort_by(|a, b| a.0.total_cmp(&b.0));
 
-        for (a_entity, aabb) in actor_query.iter() {
-            if aabb.raycast(transform.translation, movement_vector).is_none() {
-                continue;
+        let mut spent = false;
+        for (distance, a_entity) in hits {
+            impacts.hit(bullet, a_entity);
+
+            match pierce.as_mut() {
+                Some(pierce) if pierce.remaining > 0 => {
+                    pierce.hit.push(a_entity);
+                    pierce.remaining -= 1;
+                },
+                _ => {
+                    impacts.explode(bullet, explosive, origin + direction * distance);
+                    spent = true;
+                    break;
+                },
             }
+        }
+
+        if spent {
+            bullets.release(b_entity);
+            continue;
+        }
+
+        let Some((distance, wall)) = wall else {
+            continue;
+        };
 
-            commands.entity(a_entity).insert(Hit);
-            commands.entity(b_entity).insert(Hit);
+        let point = origin + direction * distance;
+        match ricochet {
+            Some(mut ricochet) if ricochet.0 > 0 => {
+                let normal = wall.normal_at(point);
+                let movement = path.movement;
+                path.movement = movement - 2.0 * movement.dot(normal) * normal;
+                transform.translation = point + normal * RICOCHET_CLEARANCE;
+                ricochet.0 -= 1;
+            },
+            _ => {
+                impacts.explode(bullet, explosive, point);
+                bullets.release(b_entity);
+            },
         }
     }
 }
 
-fn slow_down_bullets_that_hit(
-    mut bullet_query: Query<(Entity, &mut Path, &Hit), With<Bullet>>,
-    mut commands: Commands,
+/// Turns homing bullets towards where their target is going to be, a bit at a time
+fn steer_homing_bullets(
+    mut bullet_query: Query<(&Bullet, &Homing, &mut Path, &Transform)>,
+    target_query: Query<(Entity, &Transform, &super::Faction, Option<&Path>), Without<Bullet>>,
+    res_time: Res<Time>,
 ) {
-    for (entity, mut path, _) in bullet_query.iter_mut() {
-        path.velocity -= 200.0;
+    for (bullet, homing, mut path, transform) in bullet_query.iter_mut() {
+        let origin = transform.translation;
+
+        let closest = target_query.iter()
+            .filter(|(entity, _, faction, _)| **faction != bullet.faction && *entity != bullet.owner)
+            .filter(|(_, target, _, _)| target.translation.distance(origin) <= homing.range)
+            .min_by(|(_, a, _, _), (_, b, _, _)| a.translation.distance_squared(origin).total_cmp(&b.translation.distance_squared(origin)));
+
+        let Some((_, target, _, target_path)) = closest else {
+            continue;
+        };
+
+        let target_velocity = target_path.map_or(Vec3::ZERO, |target_path| target_path.movement);
+        let aim = lead_target(origin, target.translation, target_velocity, path.movement.length());
 
-        commands.entity(entity).remove::<Hit>();
+        let angle = path.movement.truncate().angle_between((aim - origin).truncate());
+        if angle.is_nan() {
+            continue;
+        }
+
+        let max_turn = homing.turn_rate * res_time.delta_seconds();
+        path.movement = Quat::from_rotation_z(angle.clamp(-max_turn, max_turn)) * path.movement;
     }
 }
 
@@ -99,31 +422,35 @@ fn move_bullets(
     }
 }
 
-fn lower_bullet_velocity(
-    mut bullet_query: Query<(&mut Path, &mut BulletDropoff), With<Bullet>>,
+/// Sets how fast every bullet goes this update, from how far along its flight it is
+fn fly_bullets(
+    mut bullet_query: Query<(&mut Path, &mut Flight), With<Bullet>>,
     res_time: Res<Time>,
 ) {
-    for (mut path, mut bullet_dropoff) in bullet_query.iter_mut() {
-        path.velocity -= res_time.delta_seconds() * bullet_dropoff.0 * bullet_dropoff.0;
+    let delta = res_time.delta_seconds();
+    if delta <= 0.0 {
+        return;
+    }
 
-        bullet_dropoff.0 += 0.05;
+    for (mut path, mut flight) in bullet_query.iter_mut() {
+        let step = flight.advance(delta);
+        let direction = path.movement.normalize_or_zero();
 
-        // The borrow checker is the bane of my existance
-        let velocity = path.velocity;
-        path.movement *= velocity;
+        path.velocity = step / delta;
+        path.movement = direction * path.velocity;
     }
 }
 
 fn remove_stopped_bullets(
-    bullet_query: Query<(&Path, Entity), With<Bullet>>,
-    mut commands: Commands,
+    bullet_query: Query<(&Flight, Entity), With<Bullet>>,
+    mut bullets: Bullets,
 ) {
-    for (path, entity) in bullet_query.iter() {
-        if path.velocity > 0.0 {
+    for (flight, entity) in bullet_query.iter() {
+        if !flight.is_over() {
             continue;
         }
 
-        commands.entity(entity).despawn();
+        bullets.release(entity);
     }
 }
 
@@ -133,17 +460,94 @@ pub struct BulletPlugin;
 impl Plugin for BulletPlugin {
     fn build(&self, app: &mut App) {
         app
-            .insert_resource(ShootCooldown(0.0))
+            .init_resource::<BulletPool>()
+            .add_systems(Startup, fill_bullet_pool)
+            .add_systems(Last, recycle_bullets)
             .add_systems(Update, (
-                spawn_bullets,
+                fly_bullets.before(steer_homing_bullets),
+                steer_homing_bullets.before(check_for_collisions),
                 check_for_collisions.before(move_bullets),
-                slow_down_bullets_that_hit,
                 move_bullets,
-                lower_bullet_velocity,
                 // prepare for panics if you don't do this
                 remove_stopped_bullets
-                    .after(check_for_collisions)
+                    .after(move_bullets)
                     .after(crate::world::set_field_coords),
             ));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flights_ignore_the_frame_rate() {
+        let ballistics = Ballistics { range: 1000.0, end_speed: 0.2, lifetime: 10.0 };
+        let mut slow = Flight::new(2000.0, ballistics);
+        let mut fast = Flight::new(2000.0, ballistics);
+
+        for _ in 0..30 {
+            slow.advance(1.0 / 30.0);
+        }
+        for _ in 0..144 {
+            fast.advance(1.0 / 144.0);
+        }
+
+        assert!((slow.travelled - fast.travelled).abs() < 0.01);
+        assert!(slow.travelled < ballistics.range);
+        assert!(ballistics.speed_at(2000.0, slow.travelled) < 2000.0);
+
+        // never slowing down, it's stopped by the range instead
+        let mut flight = Flight::new(2000.0, Ballistics { end_speed: 1.0, ..ballistics });
+        flight.advance(1.0);
+        assert_eq!(flight.travelled, 1000.0);
+        assert!(flight.is_over());
+    }
+
+    #[test]
+    fn released_bullets_wait_for_recycling() {
+        use bevy::ecs::system::RunSystemOnce;
+
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<Image>()
+            .init_resource::<BulletPool>();
+        let world = &mut app.world;
+
+        let fire = |mut bullets: Bullets| {
+            let ballistics = Ballistics { range: 1000.0, end_speed: 1.0, lifetime: 10.0 };
+            let bullet = Bullet {
+                owner: Entity::PLACEHOLDER,
+                faction: super::super::Faction::Player,
+                damage: 1.0,
+                effect: None,
+            };
+            bullets.fire(
+                Transform::default(),
+                Vec3::X,
+                Flight::new(100.0, ballistics),
+                bullet,
+                DEFAULT_BULLET_SPRITE,
+                DEFAULT_BULLET_SIZE,
+            ).id()
+        };
+
+        let first = world.run_system_once(fire);
+        world.run_system_once(move |mut bullets: Bullets| {
+            bullets.release(first);
+            bullets.release(first);
+        });
+
+        let pool = world.resource::<BulletPool>();
+        assert_eq!(pool.released, HashSet::from([first]));
+        assert!(pool.free.is_empty());
+
+        // still being put back, so it's not handed out again yet
+        let second = world.run_system_once(fire);
+        assert_ne!(second, first);
+
+        world.run_system_once(recycle_bullets);
+        assert_eq!(world.resource::<BulletPool>().free, vec![first]);
+        assert_eq!(world.run_system_once(fire), first);
+    }
+}
@@ -0,0 +1,284 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+use ranger_ai::ThreatEvent;
+use crate::world::physics::CollisionEvent;
+use super::{Faction, Health};
+
+// times per second an invulnerable sprite switches between shown and hidden
+const INVULNERABILITY_FLASH_RATE: f32 = 10.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum DamageKind {
+    Kinetic,
+    Explosive,
+    Fire,
+    Poison,
+}
+
+/// Everything that hurts goes through here, see `apply_damage`
+#[derive(Event, Debug, Clone, Copy)]
+pub struct DamageEvent {
+    pub target: Entity,
+    /// Whoever dealt it, not the bullet
+    pub source: Entity,
+    /// Before resistances and shields
+    pub amount: f32,
+    pub kind: DamageKind,
+    /// Damage over time, which neither triggers nor respects invulnerability
+    pub periodic: bool,
+}
+
+/// Sent once, when an actor's health runs out
+#[derive(Event, Debug, Clone, Copy)]
+pub struct DeathEvent {
+    pub entity: Entity,
+    pub killer: Entity,
+}
+
+/// How much of each kind of damage is shrugged off. 0 takes all of it, 1 none, and anything
+/// below 0 is a weakness.
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Resistances {
+    pub kinetic: f32,
+    pub explosive: f32,
+    pub fire: f32,
+    pub poison: f32,
+}
+
+impl Resistances {
+    pub fn scale(&self, kind: DamageKind, amount: f32) -> f32 {
+        let resistance = match kind {
+            DamageKind::Kinetic => self.kinetic,
+            DamageKind::Explosive => self.explosive,
+            DamageKind::Fire => self.fire,
+            DamageKind::Poison => self.poison,
+        };
+
+        amount * (1.0 - resistance).max(0.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct ShieldStats {
+    pub capacity: f32,
+    /// Seconds after the last hit before it starts coming back
+    pub regen_delay: f32,
+    /// Per second
+    pub regen_rate: f32,
+}
+
+/// Soaks up damage before `Health` does
+#[derive(Component, Debug)]
+pub struct Shield {
+    pub stats: ShieldStats,
+    pub current: f32,
+    since_hit: f32,
+}
+
+impl Shield {
+    pub fn new(stats: ShieldStats) -> Self {
+        Self { stats, current: stats.capacity, since_hit: 0.0 }
+    }
+
+    /// Takes as much of `amount` as it can, and returns what's left for the health
+    pub fn absorb(&mut self, amount: f32) -> f32 {
+        let absorbed = amount.min(self.current);
+        self.current -= absorbed;
+        self.since_hit = 0.0;
+
+        amount - absorbed
+    }
+
+    /// Changes the stats, keeping the current pool at or below the new capacity
+    pub fn set_stats(&mut self, stats: ShieldStats) {
+        self.stats = stats;
+        self.current = self.current.min(stats.capacity);
+    }
+}
+
+/// Damage dealt to the other side by bumping into them
+#[derive(Component, Debug, Clone, Copy)]
+pub struct ContactDamage(pub f32);
+
+/// Ignores all damage for `duration` seconds after getting hurt
+#[derive(Component, Debug)]
+pub struct Invulnerability {
+    pub duration: f32,
+    remaining: f32,
+}
+
+impl Invulnerability {
+    pub fn new(duration: f32) -> Self {
+        Self { duration, remaining: 0.0 }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.remaining > 0.0
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn apply_damage(
+    mut damage_events: EventReader<DamageEvent>,
+    mut health_query: Query<(&mut Health, Option<&Resistances>, Option<&mut Shield>, Option<&mut Invulnerability>)>,
+    mut threat_events: EventWriter<ThreatEvent>,
+    mut death_events: EventWriter<DeathEvent>,
+) {
+    for damage in damage_events.read() {
+        let Ok((mut health, resistances, shield, invulnerability)) = health_query.get_mut(damage.target) else {
+            continue;
+        };
+
+        // the dead don't get any deader
+        if health.is_dead() {
+            continue;
+        }
+
+        if let Some(mut invulnerability) = invulnerability.filter(|_| !damage.periodic) {
+            if invulnerability.is_active() {
+                continue;
+            }
+
+            invulnerability.remaining = invulnerability.duration;
+        }
+
+        let amount = resistances.map_or(damage.amount, |resistances| resistances.scale(damage.kind, damage.amount));
+        threat_events.send(ThreatEvent { entity: damage.target, source: damage.source, amount });
+
+        let amount = match shield {
+            Some(mut shield) => shield.absorb(amount),
+            None => amount,
+        };
+
+        if health.damage(amount) {
+            death_events.send(DeathEvent { entity: damage.target, killer: damage.source });
+        }
+    }
+}
+
+/// Turns actors bumping into the other side into damage
+fn deal_contact_damage(
+    mut collision_events: EventReader<CollisionEvent>,
+    actor_query: Query<(&Faction, Option<&ContactDamage>)>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    for collision in collision_events.read() {
+        for (attacker, target) in [(collision.first, collision.second), (collision.second, collision.first)] {
+            let Ok([(attacker_faction, contact_damage), (target_faction, _)]) = actor_query.get_many([attacker, target]) else {
+                continue;
+            };
+
+            let Some(contact_damage) = contact_damage else {
+                continue;
+            };
+
+            if attacker_faction == target_faction {
+                continue;
+            }
+
+            damage_events.send(DamageEvent { target, source: attacker, amount: contact_damage.0, kind: DamageKind::Kinetic, periodic: false });
+        }
+    }
+}
+
+fn regenerate_shields(
+    mut shield_query: Query<&mut Shield>,
+    res_time: Res<Time>,
+) {
+    for mut shield in shield_query.iter_mut() {
+        shield.since_hit += res_time.delta_seconds();
+
+        if shield.since_hit < shield.stats.regen_delay {
+            continue;
+        }
+
+        let regenerated = shield.current + shield.stats.regen_rate * res_time.delta_seconds();
+        shield.current = regenerated.min(shield.stats.capacity);
+    }
+}
+
+/// Blinks whatever's invulnerable right now
+fn tick_invulnerability(
+    mut actor_query: Query<(&mut Invulnerability, &mut Visibility)>,
+    res_time: Res<Time>,
+) {
+    for (mut invulnerability, mut visibility) in actor_query.iter_mut() {
+        if !invulnerability.is_active() {
+            continue;
+        }
+
+        invulnerability.remaining -= res_time.delta_seconds();
+
+        let shown = !invulnerability.is_active()
+            || (invulnerability.remaining * INVULNERABILITY_FLASH_RATE * std::f32::consts::PI).sin() >= 0.0;
+        *visibility = match shown {
+            true => Visibility::Inherited,
+            false => Visibility::Hidden,
+        };
+    }
+}
+
+/// The player sticks around when dying, everybody else goes away
+pub fn despawn_dead(
+    mut death_events: EventReader<DeathEvent>,
+    actor_query: Query<(), Without<super::player::Player>>,
+    mut commands: Commands,
+) {
+    for death in death_events.read() {
+        if actor_query.contains(death.entity) {
+            commands.entity(death.entity).despawn();
+        }
+    }
+}
+
+pub struct DamagePlugin;
+
+impl Plugin for DamagePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_event::<DamageEvent>()
+            .add_event::<DeathEvent>()
+            .add_systems(Update, (
+                deal_contact_damage.after(crate::world::physics::detect_actor_collisions),
+                apply_damage
+                    .after(super::bullet::check_for_collisions)
+                    .after(deal_contact_damage),
+                tick_invulnerability.after(apply_damage),
+                regenerate_shields.after(apply_damage),
+                // everything that inserts onto actors goes first, inserting onto an entity that's
+                // already gone panics
+                despawn_dead
+                    .after(apply_damage)
+                    .after(crate::world::set_field_coords)
+                    .after(ranger_ai::squad::form_squads)
+                    .after(super::status::apply_status_modifiers)
+                    .after(super::archetype::apply_archetype_changes),
+            ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_killing_blow_kills() {
+        let mut health = Health::new(100.0);
+
+        assert!(!health.damage(60.0));
+        assert!(health.damage(60.0));
+        assert!(!health.damage(60.0));
+        assert_eq!(health.current, 0.0);
+    }
+
+    #[test]
+    fn shields_soak_up_what_resistances_let_through() {
+        let resistances = Resistances { kinetic: 0.5, fire: -0.5, ..default() };
+        let mut shield = Shield::new(ShieldStats { capacity: 20.0, regen_delay: 2.0, regen_rate: 10.0 });
+
+        assert_eq!(shield.absorb(resistances.scale(DamageKind::Kinetic, 30.0)), 0.0);
+        assert_eq!(shield.absorb(resistances.scale(DamageKind::Fire, 10.0)), 10.0);
+        assert_eq!(resistances.scale(DamageKind::Poison, 10.0), 10.0);
+    }
+}
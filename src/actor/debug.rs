@@ -0,0 +1,256 @@
+use bevy::prelude::*;
+use ranger_physics::{AABB, Path};
+use ranger_ai::{InfluenceLayer, InfluenceMap, NavPath, Perception, StateMachine, Target};
+
+const LABEL_OFFSET: f32 = 40.0;
+// flow arrows weaker than this aren't worth drawing
+const MIN_FLOW: f32 = 0.01;
+
+/// Which parts of the AI overlay get drawn, toggled with F1 to F6
+#[derive(Resource, Debug)]
+pub struct AiDebugLayers {
+    pub targets: bool,
+    pub paths: bool,
+    pub perception: bool,
+    pub states: bool,
+    pub steering: bool,
+    pub flow_field: bool,
+}
+
+impl Default for AiDebugLayers {
+    fn default() -> Self {
+        Self {
+            targets: true,
+            paths: true,
+            perception: false,
+            states: true,
+            steering: false,
+            flow_field: false,
+        }
+    }
+}
+
+/// Floats above the entity whose state machine it shows
+#[derive(Component)]
+struct StateLabel(Entity);
+
+fn toggle_layers(
+    mut res_layers: ResMut<AiDebugLayers>,
+    res_keyboard_input: Res<ButtonInput<KeyCode>>,
+) {
+    let layers = &mut *res_layers;
+    let toggles = [
+        (KeyCode::F1, &mut layers.targets),
+        (KeyCode::F2, &mut layers.paths),
+        (KeyCode::F3, &mut layers.perception),
+        (KeyCode::F4, &mut layers.states),
+        (KeyCode::F5, &mut layers.steering),
+        (KeyCode::F6, &mut layers.flow_field),
+    ];
+
+    for (key, layer) in toggles {
+        if res_keyboard_input.just_pressed(key) {
+            *layer = !*layer;
+        }
+    }
+}
+
+/// A line to the target while it's seen, the last known position and search circle after that
+fn draw_targets(
+    actor_query: Query<(&Transform, &Target)>,
+    res_layers: Res<AiDebugLayers>,
+    res_time: Res<Time>,
+    mut gizmos: Gizmos,
+) {
+    if !res_layers.targets {
+        return;
+    }
+
+    for (transform, target) in actor_query.iter() {
+        let position = transform.translation.truncate();
+
+        if let Some(point) = target.point {
+            gizmos.line_2d(position, point.truncate(), Color::RED);
+            continue;
+        }
+
+        let Some(last_known) = target.last_known else {
+            continue;
+        };
+
+        if target.is_searching(res_time.elapsed_seconds()) {
+            gizmos.line_2d(position, last_known.truncate(), Color::ORANGE);
+            gizmos.circle_2d(last_known.truncate(), target.search_radius, Color::ORANGE);
+        }
+    }
+}
+
+fn draw_paths(
+    actor_query: Query<(&Transform, &NavPath)>,
+    res_layers: Res<AiDebugLayers>,
+    mut gizmos: Gizmos,
+) {
+    if !res_layers.paths {
+        return;
+    }
+
+    for (transform, nav_path) in actor_query.iter() {
+        let waypoints = nav_path.remaining();
+        if waypoints.is_empty() {
+            continue;
+        }
+
+        gizmos.linestrip_2d(
+            std::iter::once(transform.translation.truncate()).chain(waypoints.iter().map(|waypoint| waypoint.truncate())),
+            Color::YELLOW,
+        );
+
+        for waypoint in waypoints {
+            gizmos.circle_2d(waypoint.truncate(), 4.0, Color::YELLOW);
+        }
+    }
+}
+
+fn draw_perception(
+    actor_query: Query<(&Transform, &Perception)>,
+    res_layers: Res<AiDebugLayers>,
+    mut gizmos: Gizmos,
+) {
+    if !res_layers.perception {
+        return;
+    }
+
+    for (transform, perception) in actor_query.iter() {
+        let position = transform.translation.truncate();
+
+        if perception.field_of_view >= std::f32::consts::TAU {
+            gizmos.circle_2d(position, perception.range, Color::CYAN);
+            continue;
+        }
+
+        let facing = Perception::facing(transform);
+        let half = perception.field_of_view / 2.0;
+        // arcs are measured clockwise from y
+        let direction_angle = std::f32::consts::FRAC_PI_2 - facing.y.atan2(facing.x);
+
+        gizmos.arc_2d(position, direction_angle, perception.field_of_view, perception.range, Color::CYAN);
+        for edge in [-half, half] {
+            let edge = Vec2::from_angle(edge).rotate(facing) * perception.range;
+            gizmos.line_2d(position, position + edge, Color::CYAN);
+        }
+    }
+}
+
+/// Where the actor is actually going, and where the next waypoint would have it go
+fn draw_steering(
+    actor_query: Query<(&Transform, &Path, Option<&NavPath>)>,
+    res_layers: Res<AiDebugLayers>,
+    mut gizmos: Gizmos,
+) {
+    if !res_layers.steering {
+        return;
+    }
+
+    for (transform, path, nav_path) in actor_query.iter() {
+        let position = transform.translation.truncate();
+
+        if let Some(waypoint) = nav_path.and_then(|nav_path| nav_path.remaining().first()) {
+            let desired = (waypoint.truncate() - position).normalize_or_zero() * path.velocity;
+            gizmos.arrow_2d(position, position + desired, Color::WHITE);
+        }
+
+        if path.movement != Vec3::ZERO {
+            gizmos.arrow_2d(position, position + path.movement.truncate(), Color::GREEN);
+        }
+    }
+}
+
+/// Arrows pointing down the player threat, which is the way fleeing enemies go
+fn draw_flow_field(
+    res_influence_map: Option<Res<InfluenceMap>>,
+    res_layers: Res<AiDebugLayers>,
+    mut gizmos: Gizmos,
+) {
+    let Some(influence_map) = res_influence_map.filter(|_| res_layers.flow_field) else {
+        return;
+    };
+
+    let cell_size = influence_map.cell_size();
+    let layer = InfluenceLayer::PlayerThreat;
+
+    for (centre, value) in influence_map.values(layer) {
+        let sample = |offset: Vec2| influence_map.value(layer, (centre + offset).extend(0.0));
+        let dx = Vec2::new(cell_size.x, 0.0);
+        let dy = Vec2::new(0.0, cell_size.y);
+
+        let gradient = Vec2::new(sample(dx) - sample(-dx), sample(dy) - sample(-dy));
+        if value <= 0.0 || gradient.length() < MIN_FLOW {
+            continue;
+        }
+
+        let arrow = -gradient.normalize() * cell_size.min_element() * 0.4;
+        gizmos.arrow_2d(centre - arrow / 2.0, centre + arrow / 2.0, Color::PURPLE);
+    }
+}
+
+/// Keeps a text label with the current state above everything that has a state machine
+fn update_state_labels(
+    machine_query: Query<(Entity, &StateMachine, &Transform, Option<&AABB>), Without<StateLabel>>,
+    mut label_query: Query<(Entity, &StateLabel, &mut Text, &mut Transform, &mut Visibility)>,
+    mut commands: Commands,
+    res_layers: Res<AiDebugLayers>,
+) {
+    let mut labelled = vec![];
+
+    for (label_entity, label, mut text, mut transform, mut visibility) in label_query.iter_mut() {
+        let Ok((_, machine, owner_transform, aabb)) = machine_query.get(label.0) else {
+            commands.entity(label_entity).despawn();
+            continue;
+        };
+
+        labelled.push(label.0);
+        text.sections[0].value = format!("{:?}", machine.state());
+        transform.translation = owner_transform.translation
+            + Vec3::new(0.0, aabb.map_or(LABEL_OFFSET, |aabb| aabb.height / 2.0 + 10.0), 1.0);
+        *visibility = match res_layers.states {
+            true => Visibility::Visible,
+            false => Visibility::Hidden,
+        };
+    }
+
+    for (entity, machine, _, _) in machine_query.iter() {
+        if labelled.contains(&entity) {
+            continue;
+        }
+
+        commands.spawn((
+            StateLabel(entity),
+            Text2dBundle {
+                text: Text::from_section(
+                    format!("{:?}", machine.state()),
+                    TextStyle { font_size: 14.0, color: Color::WHITE, ..default() },
+                ),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+        ));
+    }
+}
+
+pub struct AiDebugPlugin;
+
+impl Plugin for AiDebugPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<AiDebugLayers>()
+            .add_systems(Update, (
+                toggle_layers,
+                draw_targets.after(super::move_actors),
+                draw_paths.after(super::move_actors),
+                draw_perception.after(super::move_actors),
+                draw_steering.after(super::move_actors),
+                draw_flow_field,
+                update_state_labels.after(super::move_actors),
+            ));
+    }
+}
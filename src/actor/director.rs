@@ -0,0 +1,490 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+use serde::Deserialize;
+use ranger_physics::AABB;
+use ranger_ai::PatrolRoute;
+use crate::world::map::Grid;
+use super::archetype::{EnemyArchetype, EnemySpawner};
+
+// how often a telegraph marker blinks per second
+const TELEGRAPH_PULSE_RATE: f32 = 4.0;
+// seconds a spawn keeps to its rules before it settles for appearing close to the player,
+// and before it's given up on altogether
+const SPAWN_PATIENCE: f32 = 3.0;
+const SPAWN_GIVE_UP: f32 = 6.0;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WaveEnemy {
+    pub archetype: String,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WaveDefinition {
+    pub enemies: Vec<WaveEnemy>,
+    /// Seconds between two spawns
+    pub interval: f32,
+    /// Used instead of the map's spawn fields, if there are any
+    #[serde(default)]
+    pub spawn_points: Vec<(f32, f32)>,
+}
+
+/// How much harder every wave past the last defined one gets, compounding
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Escalation {
+    pub count: f32,
+    pub health: f32,
+    pub interval: f32,
+}
+
+/// Where enemies are allowed to appear
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SpawnRules {
+    pub min_player_distance: f32,
+    /// Moves every spawn point just past the closest edge of the screen, so enemies walk in
+    pub off_screen: bool,
+    /// Seconds a marker shows where an enemy is about to appear
+    pub telegraph: f32,
+}
+
+impl Default for SpawnRules {
+    fn default() -> Self {
+        Self {
+            min_player_distance: 250.0,
+            off_screen: false,
+            telegraph: 1.0,
+        }
+    }
+}
+
+/// Whatever's in the way of spawning somewhere
+pub struct SpawnSurroundings<'a> {
+    pub player: Option<Vec3>,
+    pub actors: &'a [AABB],
+    pub grid: Option<&'a Grid>,
+    /// The part of the world that's on screen
+    pub view: Rect,
+}
+
+impl SpawnRules {
+    /// A random one of `candidates` where something of `size` fits: far enough from the player,
+    /// not in a solid field and not on top of another actor. None if there's no such point.
+    pub fn choose(&self, candidates: &[Vec3], size: Vec2, surroundings: &SpawnSurroundings) -> Option<Vec3> {
+        let valid: Vec<Vec3> = candidates.iter()
+            .map(|point| match self.off_screen {
+                true => past_closest_edge(*point, size, surroundings.view),
+                false => *point,
+            })
+            .filter(|point| {
+                let bounding_box = AABB::new(*point, size);
+
+                !surroundings.player.is_some_and(|player| player.distance(*point) < self.min_player_distance)
+                    && !surroundings.grid.is_some_and(|grid| grid.overlaps_solid(&bounding_box))
+                    && surroundings.actors.iter().all(|actor| actor.static_static(&bounding_box).is_none())
+            })
+            .collect();
+
+        fastrand::choice(valid)
+    }
+}
+
+/// As far out as `confine_to_screen` lets something of `size` be, on the side of `view` that's
+/// closest to `point`
+fn past_closest_edge(point: Vec3, size: Vec2, view: Rect) -> Vec3 {
+    let reach = view.half_size() + size / 2.0;
+    let offset = point.truncate() - view.center();
+
+    match reach.x - offset.x.abs() < reach.y - offset.y.abs() {
+        true => Vec3::new(view.center().x + reach.x * offset.x.signum(), point.y, point.z),
+        false => Vec3::new(point.x, view.center().y + reach.y * offset.y.signum(), point.z),
+    }
+}
+
+/// The survival loop, loaded from `assets/waves/*.waves.ron`
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct WaveDefinitions {
+    /// Seconds before the first wave
+    pub warmup: f32,
+    /// Seconds between clearing a wave and starting the next one
+    pub break_duration: f32,
+    pub escalation: Escalation,
+    #[serde(default)]
+    pub spawning: SpawnRules,
+    pub waves: Vec<WaveDefinition>,
+}
+
+/// Everything needed to run one wave
+#[derive(Debug, Clone, PartialEq)]
+pub struct WavePlan {
+    /// Archetype names, in spawn order
+    pub spawns: Vec<String>,
+    pub interval: f32,
+    pub health_scale: f32,
+    pub spawn_points: Vec<Vec3>,
+}
+
+impl WaveDefinitions {
+    /// The plan for wave `index`, counting from 0. Once the defined waves run out the last one
+    /// keeps repeating, escalated once more every time.
+    pub fn plan(&self, index: usize) -> Option<WavePlan> {
+        let last = self.waves.len().checked_sub(1)?;
+        let wave = &self.waves[index.min(last)];
+        let escalations = index.saturating_sub(last) as i32;
+
+        let count_scale = self.escalation.count.powi(escalations);
+        let mut spawns: Vec<String> = wave.enemies.iter()
+            .flat_map(|enemy| {
+                let count = (enemy.count as f32 * count_scale).round() as usize;
+                std::iter::repeat_n(enemy.archetype.clone(), count)
+            })
+            .collect();
+        fastrand::shuffle(&mut spawns);
+
+        Some(WavePlan {
+            spawns,
+            interval: wave.interval * self.escalation.interval.powi(escalations),
+            health_scale: self.escalation.health.powi(escalations),
+            spawn_points: wave.spawn_points.iter().map(|(x, y)| Vec3::new(*x, *y, 0.0)).collect(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Phase {
+    /// Waiting until the given time before starting the next wave
+    Break(f32),
+    /// Spawning the rest of the plan, the next one at the given time
+    Spawning(f32),
+    /// Everything's out, waiting for it to die
+    Fighting,
+    /// There are no waves to run
+    Stopped,
+}
+
+#[derive(Resource)]
+pub struct WaveDirector {
+    definitions: Handle<WaveDefinitions>,
+    /// The wave currently running, or the one that's next during a break
+    wave: usize,
+    phase: Option<Phase>,
+    plan: Option<WavePlan>,
+    spawned: usize,
+}
+
+/// Marks enemies that belong to the current wave
+#[derive(Component)]
+pub struct WaveMember;
+
+/// Waves count from 0
+#[derive(Event, Debug, Clone, Copy)]
+pub enum WaveEvent {
+    Started(usize),
+    Cleared(usize),
+}
+
+/// An enemy that's about to appear, a wave member until it does
+#[derive(Component)]
+struct PendingSpawn {
+    archetype: String,
+    health_scale: f32,
+    route: Option<PatrolRoute>,
+    /// Falls back to the map's spawn fields when empty
+    candidates: Vec<Vec3>,
+    rules: SpawnRules,
+    /// When it started looking for a spot
+    since: f32,
+}
+
+/// The marker of a pending spawn that found its spot
+#[derive(Component)]
+struct Telegraph {
+    point: Vec3,
+    remaining: f32,
+}
+
+fn load_waves(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    commands.insert_resource(WaveDirector {
+        definitions: asset_server.load("waves/survival.waves.ron"),
+        wave: 0,
+        phase: None,
+        plan: None,
+        spawned: 0,
+    });
+}
+
+fn direct_waves(
+    member_query: Query<(), With<WaveMember>>,
+    patrol_routes_query: Query<&crate::world::map::PatrolRoutes>,
+    mut commands: Commands,
+    mut res_director: ResMut<WaveDirector>,
+    res_definitions: Res<Assets<WaveDefinitions>>,
+    res_time: Res<Time>,
+    mut wave_events: EventWriter<WaveEvent>,
+) {
+    let Some(definitions) = res_definitions.get(&res_director.definitions) else {
+        return;
+    };
+
+    let now = res_time.elapsed_seconds();
+    let director = &mut *res_director;
+
+    match director.phase.clone() {
+        None => director.phase = Some(Phase::Break(now + definitions.warmup)),
+        Some(Phase::Break(until)) => {
+            if now < until {
+                return;
+            }
+
+            let Some(plan) = definitions.plan(director.wave) else {
+                warn!("no waves defined, stopping the wave director");
+                director.phase = Some(Phase::Stopped);
+                return;
+            };
+
+            director.plan = Some(plan);
+            director.spawned = 0;
+            director.phase = Some(Phase::Spawning(now));
+            wave_events.send(WaveEvent::Started(director.wave));
+        },
+        Some(Phase::Spawning(next)) => {
+            let Some(plan) = director.plan.as_ref() else {
+                director.phase = Some(Phase::Fighting);
+                return;
+            };
+
+            if director.spawned >= plan.spawns.len() {
+                director.phase = Some(Phase::Fighting);
+                return;
+            }
+
+            if now < next {
+                return;
+            }
+
+            let route = patrol_routes_query.get_single().ok()
+                .filter(|patrol_routes| !patrol_routes.0.is_empty())
+                .map(|patrol_routes| patrol_routes.0[director.spawned % patrol_routes.0.len()].clone());
+
+            commands.spawn((
+                PendingSpawn {
+                    archetype: plan.spawns[director.spawned].clone(),
+                    health_scale: plan.health_scale,
+                    route,
+                    candidates: plan.spawn_points.clone(),
+                    rules: definitions.spawning,
+                    since: now,
+                },
+                WaveMember,
+            ));
+
+            director.spawned += 1;
+            director.phase = Some(Phase::Spawning(now + plan.interval));
+        },
+        Some(Phase::Fighting) => {
+            if !member_query.is_empty() {
+                return;
+            }
+
+            wave_events.send(WaveEvent::Cleared(director.wave));
+            director.wave += 1;
+            director.phase = Some(Phase::Break(now + definitions.break_duration));
+        },
+        Some(Phase::Stopped) => (),
+    }
+}
+
+/// Finds a spot for every pending spawn and puts a marker there. Spawns that don't fit anywhere
+/// right now try again next frame, stop minding the player after a while and are dropped if
+/// that doesn't help either, so the wave can still be cleared.
+fn place_spawns(
+    pending_query: Query<(Entity, &PendingSpawn, Option<&Telegraph>)>,
+    actor_query: Query<(&AABB, Has<super::player::Player>), With<super::Faction>>,
+    grid_query: Query<&Grid>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut commands: Commands,
+    res_archetypes: Res<Assets<EnemyArchetype>>,
+    res_time: Res<Time>,
+) {
+    let Ok(window) = window_query.get_single() else {
+        return;
+    };
+
+    let size_of = |name: &str| res_archetypes.iter()
+        .find(|(_, archetype)| archetype.name == name)
+        .map(|(_, archetype)| archetype.size());
+
+    let grid = grid_query.get_single().ok();
+    let map_points = grid.map_or_else(Vec::new, Grid::spawn_points);
+    let player = actor_query.iter().find(|(_, is_player)| *is_player).map(|(aabb, _)| aabb.point);
+    // markers already out count as taken, so two enemies don't appear on top of each other
+    let mut actors: Vec<AABB> = actor_query.iter().map(|(aabb, _)| aabb.clone())
+        .chain(pending_query.iter().filter_map(|(_, pending, telegraph)| {
+            Some(AABB::new(telegraph?.point, size_of(&pending.archetype)?))
+        }))
+        .collect();
+    let view = Rect::from_center_size(Vec2::ZERO, Vec2::new(window.width(), window.height()));
+
+    for (entity, pending, telegraph) in pending_query.iter() {
+        if telegraph.is_some() {
+            continue;
+        }
+
+        // can't tell what it'd overlap until its archetype is loaded, if there even is one by
+        // that name. either way it gets dropped eventually if it never shows up
+        let Some(size) = size_of(&pending.archetype) else {
+            if res_time.elapsed_seconds() - pending.since > SPAWN_GIVE_UP {
+                warn!("no enemy archetype called {}, dropping its spawn", pending.archetype);
+                commands.entity(entity).despawn();
+            }
+            continue;
+        };
+
+        let candidates = match pending.candidates.is_empty() {
+            true => &map_points,
+            false => &pending.candidates,
+        };
+
+        // without a map there's nothing to fall back to yet, it might still be loading
+        if candidates.is_empty() && grid.is_some() {
+            warn!("nowhere to spawn {}, the wave and the map have no spawn points", pending.archetype);
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        let waited = res_time.elapsed_seconds() - pending.since;
+        let rules = match waited > SPAWN_PATIENCE {
+            true => SpawnRules { min_player_distance: 0.0, ..pending.rules },
+            false => pending.rules,
+        };
+
+        let surroundings = SpawnSurroundings { player, actors: &actors, grid, view };
+        let Some(point) = rules.choose(candidates, size, &surroundings) else {
+            if waited > SPAWN_GIVE_UP {
+                warn!("couldn't find a free spot to spawn {} in {} seconds, dropping it", pending.archetype, SPAWN_GIVE_UP);
+                commands.entity(entity).despawn();
+            }
+            continue;
+        };
+
+        // off screen spawns get their marker at the edge, where they're going to walk in
+        let marker = point.truncate().clamp(view.min + size / 2.0, view.max - size / 2.0);
+
+        commands.entity(entity).insert((
+            Telegraph { point, remaining: pending.rules.telegraph },
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::RED,
+                    custom_size: Some(size),
+                    ..default()
+                },
+                transform: Transform::from_translation(marker.extend(-0.5)),
+                ..default()
+            },
+        ));
+        actors.push(AABB::new(point, size));
+    }
+}
+
+/// Blinks the markers, and swaps them for the enemy once they run out
+fn hatch_telegraphs(
+    mut telegraph_query: Query<(Entity, &PendingSpawn, &mut Telegraph, &mut Sprite)>,
+    mut enemy_spawner: EnemySpawner,
+    mut commands: Commands,
+    res_time: Res<Time>,
+) {
+    for (entity, pending, mut telegraph, mut sprite) in telegraph_query.iter_mut() {
+        telegraph.remaining -= res_time.delta_seconds();
+
+        if telegraph.remaining > 0.0 {
+            let pulse = (telegraph.remaining * TELEGRAPH_PULSE_RATE * std::f32::consts::PI).cos().abs();
+            sprite.color.set_a(0.25 + 0.5 * pulse);
+            continue;
+        }
+
+        commands.entity(entity).despawn();
+        match enemy_spawner.spawn(&pending.archetype, telegraph.point, pending.route.clone(), pending.health_scale) {
+            Some(mut enemy) => {
+                enemy.insert(WaveMember);
+            },
+            // it was there when the marker went up, so the archetype got renamed or removed since
+            None => warn!("couldn't spawn {}, there's no enemy archetype by that name anymore", pending.archetype),
+        }
+    }
+}
+
+fn announce_waves(
+    mut wave_events: EventReader<WaveEvent>,
+) {
+    for event in wave_events.read() {
+        match event {
+            WaveEvent::Started(wave) => info!("wave {} started", wave + 1),
+            WaveEvent::Cleared(wave) => info!("wave {} cleared", wave + 1),
+        }
+    }
+}
+
+pub struct DirectorPlugin;
+
+impl Plugin for DirectorPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_asset::<WaveDefinitions>()
+            .register_asset_loader(ranger_ai::RonLoader::<WaveDefinitions>::new(&["waves.ron"]))
+            .add_event::<WaveEvent>()
+            .add_systems(Startup, load_waves)
+            .add_systems(Update, (
+                direct_waves,
+                place_spawns.after(direct_waves),
+                hatch_telegraphs.after(place_spawns),
+                announce_waves.after(direct_waves),
+            ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waves_escalate_past_the_last_one() {
+        let definitions: WaveDefinitions = ron::from_str(include_str!("../../assets/waves/survival.waves.ron")).unwrap();
+        let last = definitions.waves.len() - 1;
+
+        let defined = definitions.plan(last).unwrap();
+        let escalated = definitions.plan(last + 2).unwrap();
+
+        assert!(escalated.spawns.len() > defined.spawns.len());
+        assert!(escalated.health_scale > defined.health_scale);
+        assert!(escalated.interval < defined.interval);
+    }
+
+    #[test]
+    fn spawn_points_keep_clear() {
+        let mut grid = Grid::new(9, 9);
+        grid.set_solid(1, 1);
+        let actors = [AABB::new(Vec3::new(300.0, 0.0, 0.0), Vec2::splat(50.0))];
+        let surroundings = SpawnSurroundings {
+            player: Some(Vec3::ZERO),
+            actors: &actors,
+            grid: Some(&grid),
+            view: Rect::from_center_size(Vec2::ZERO, Vec2::new(1280.0, 720.0)),
+        };
+        let rules = SpawnRules { min_player_distance: 250.0, off_screen: false, telegraph: 1.0 };
+
+        let too_close = Vec3::new(100.0, 0.0, 0.0);
+        let solid = Vec3::new(-300.0, 300.0, 0.0);
+        let occupied = Vec3::new(310.0, 0.0, 0.0);
+        let free = Vec3::new(0.0, -300.0, 0.0);
+        let candidates = [too_close, solid, occupied, free];
+
+        assert_eq!(rules.choose(&candidates, Vec2::splat(50.0), &surroundings), Some(free));
+        assert_eq!(rules.choose(&candidates[..3], Vec2::splat(50.0), &surroundings), None);
+
+        // straight down is closest to the bottom edge
+        let off_screen = SpawnRules { off_screen: true, ..rules };
+        assert_eq!(off_screen.choose(&[free], Vec2::splat(50.0), &surroundings), Some(Vec3::new(0.0, -385.0, 0.0)));
+    }
+}
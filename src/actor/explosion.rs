@@ -0,0 +1,181 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+use ranger_physics::AABB;
+use crate::world::map::{FieldOccupants, Grid};
+use super::damage::{DamageEvent, DamageKind};
+
+// how much of its knockback speed something keeps after a second
+const KNOCKBACK_RETENTION: f32 = 0.02;
+const KNOCKBACK_MIN_SPEED: f32 = 5.0;
+
+/// How the damage drops off towards the edge of the radius
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum Falloff {
+    /// Full damage all the way out
+    None,
+    #[default]
+    Linear,
+    /// Stays strong for longer, then drops off quickly
+    Quadratic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct ExplosionStats {
+    pub radius: f32,
+    /// Right at the centre
+    pub damage: f32,
+    #[serde(default)]
+    pub falloff: Falloff,
+    /// Speed things right at the centre get pushed away with, falling off like the damage
+    #[serde(default)]
+    pub knockback: f32,
+    /// Solid fields shield whatever's behind them
+    #[serde(default)]
+    pub blocked_by_walls: bool,
+}
+
+impl ExplosionStats {
+    /// How much of the full damage and knockback something at `distance` gets
+    pub fn strength_at(&self, distance: f32) -> f32 {
+        if distance > self.radius {
+            return 0.0;
+        }
+
+        let fraction = distance / self.radius;
+        match self.falloff {
+            Falloff::None => 1.0,
+            Falloff::Linear => 1.0 - fraction,
+            Falloff::Quadratic => 1.0 - fraction * fraction,
+        }
+    }
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ExplosionEvent {
+    pub point: Vec3,
+    /// Whoever set it off, who doesn't get hurt by it
+    pub source: Entity,
+    pub stats: ExplosionStats,
+}
+
+/// Pushes an actor around on top of wherever it's going by itself, dying down over time
+#[derive(Component, Debug)]
+pub struct Knockback(pub Vec3);
+
+/// Hurts and pushes away everything in range of an explosion. Only looks at actors in the fields
+/// the explosion reaches.
+fn explode(
+    mut explosion_events: EventReader<ExplosionEvent>,
+    mut actor_query: Query<(&Transform, Option<&mut Knockback>), With<super::Health>>,
+    grid_query: Query<&Grid>,
+    res_occupants: Res<FieldOccupants>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut commands: Commands,
+) {
+    let Ok(grid) = grid_query.get_single() else {
+        return;
+    };
+
+    for explosion in explosion_events.read() {
+        let fields = grid.fields_in_radius(explosion.point, explosion.stats.radius);
+
+        for entity in res_occupants.in_fields(&fields) {
+            if entity == explosion.source {
+                continue;
+            }
+
+            let Ok((transform, knockback)) = actor_query.get_mut(entity) else {
+                continue;
+            };
+
+            let offset = transform.translation - explosion.point;
+            let strength = explosion.stats.strength_at(offset.length());
+            if strength <= 0.0 {
+                continue;
+            }
+
+            if explosion.stats.blocked_by_walls && !grid.line_of_sight(explosion.point, transform.translation) {
+                continue;
+            }
+
+            damage_events.send(DamageEvent {
+                target: entity,
+                source: explosion.source,
+                amount: explosion.stats.damage * strength,
+                kind: DamageKind::Explosive,
+                periodic: false,
+            });
+
+            if explosion.stats.knockback <= 0.0 {
+                continue;
+            }
+
+            let impulse = offset.normalize_or_zero() * explosion.stats.knockback * strength;
+            match knockback {
+                Some(mut knockback) => knockback.0 += impulse,
+                None => { commands.entity(entity).insert(Knockback(impulse)); },
+            }
+        }
+    }
+}
+
+fn apply_knockback(
+    mut actor_query: Query<(Entity, &mut Knockback, &mut Transform, &mut AABB)>,
+    mut commands: Commands,
+    res_time: Res<Time>,
+) {
+    for (entity, mut knockback, mut transform, mut aabb) in actor_query.iter_mut() {
+        transform.translation += knockback.0 * res_time.delta_seconds();
+        aabb.point = transform.translation;
+        knockback.0 *= KNOCKBACK_RETENTION.powf(res_time.delta_seconds());
+
+        if knockback.0.length() < KNOCKBACK_MIN_SPEED {
+            commands.entity(entity).remove::<Knockback>();
+        }
+    }
+}
+
+pub struct ExplosionPlugin;
+
+impl Plugin for ExplosionPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_event::<ExplosionEvent>()
+            .add_systems(Update, (
+                explode
+                    .after(super::bullet::check_for_collisions)
+                    .after(crate::world::set_field_coords)
+                    .before(super::damage::apply_damage),
+                apply_knockback.after(super::move_actors),
+            ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explosions_fall_off_and_stop_at_walls() {
+        let stats = ExplosionStats { radius: 100.0, damage: 50.0, falloff: Falloff::Linear, knockback: 0.0, blocked_by_walls: true };
+        assert_eq!(stats.strength_at(0.0), 1.0);
+        assert_eq!(stats.strength_at(50.0), 0.5);
+        assert_eq!(stats.strength_at(150.0), 0.0);
+        assert_eq!(ExplosionStats { falloff: Falloff::Quadratic, ..stats }.strength_at(50.0), 0.75);
+
+        // a pillar in the middle of a 3x3 grid
+        let mut grid = Grid::new(3, 3);
+        grid.set_solid(2, 2);
+        assert!(!grid.line_of_sight(Vec3::new(-75.0, 0.0, 0.0), Vec3::new(75.0, 0.0, 0.0)));
+        assert!(grid.line_of_sight(Vec3::new(-75.0, 75.0, 0.0), Vec3::new(75.0, 75.0, 0.0)));
+        assert!(!grid.line_of_sight(Vec3::new(-75.0, 75.0, 0.0), Vec3::new(75.0, -75.0, 0.0)));
+        assert!(grid.line_of_sight(Vec3::new(-300.0, 75.0, 0.0), Vec3::new(300.0, 75.0, 0.0)));
+
+        let fields = grid.fields_in_radius(Vec3::new(-75.0, 75.0, 0.0), 10.0);
+        assert_eq!(fields, vec![(1, 1)]);
+        // the pillar is only touched by the bounding box, not the circle
+        let fields = grid.fields_in_radius(Vec3::new(-75.0, 75.0, 0.0), 40.0);
+        assert_eq!(fields, vec![(1, 1), (1, 2), (2, 1), (0, 0)]);
+        assert!(grid.fields_in_radius(Vec3::ZERO, 200.0).contains(&(0, 0)));
+    }
+}
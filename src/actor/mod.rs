@@ -1,12 +1,61 @@
 use bevy::{prelude::*, window::PrimaryWindow};
 use ranger_physics::{AABB, Path};
+use ranger_ai::{blackboard::keys, BehaviorTree, Blackboard, Perception, Target, ThreatSource, ThreatTable};
 
+pub mod archetype;
 pub mod player;
 pub mod basic_enemy;
+pub mod ranged_enemy;
 pub mod bullet;
+pub mod damage;
+mod debug;
+pub mod director;
+pub mod explosion;
+pub mod pickup;
+pub mod status;
+pub mod weapon;
 
-#[derive(Component)]
-struct Health(f32);
+#[derive(Component, Debug)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.0
+    }
+
+    /// Returns whether this is what killed it
+    pub fn damage(&mut self, amount: f32) -> bool {
+        let was_alive = !self.is_dead();
+        self.current = (self.current - amount).max(0.0);
+
+        was_alive && self.is_dead()
+    }
+
+    /// Changes the maximum, keeping the current health at or below it
+    pub fn set_max(&mut self, max: f32) {
+        self.max = max;
+        self.current = self.current.min(max);
+    }
+}
+
+/// How fast an actor goes when nothing's slowing it down. Status effects work out
+/// `Path::velocity` from this, so anything changing an actor's speed for good changes this.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct BaseSpeed(pub f32);
+
+/// Which side an actor is on. Bullets don't hit their own side.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Faction {
+    Player,
+    Enemy,
+}
 
 pub fn move_actors(
     mut actor_query: Query<(
@@ -22,6 +71,55 @@ pub fn move_actors(
     }
 }
 
+/// Goes after whatever the threat table picked, as long as it can be seen
+pub fn detect_targets(
+    source_query: Query<&Transform, With<ThreatSource>>,
+    mut enemy_query: Query<(&mut Target, &ThreatTable, &Perception, &Transform), Without<ranger_ai::Stunned>>,
+    res_time: Res<Time>,
+) {
+    let now = res_time.elapsed_seconds();
+
+    for (mut enemies_target, threat_table, perception, enemy_transform) in enemy_query.iter_mut() {
+        let in_range = threat_table.current()
+            .and_then(|source| source_query.get(source).ok().map(|transform| (source, transform.translation)))
+            .filter(|(_, point)| perception.can_see(enemy_transform, *point));
+
+        let Some((source, point)) = in_range else {
+            enemies_target.remove_target();
+
+            if !enemies_target.is_searching(now) {
+                enemies_target.forget();
+            }
+            continue;
+        };
+
+        enemies_target.set_entity(source, point, now);
+    }
+}
+
+/// Trees only know about the actor what's on its blackboard
+fn update_tree_blackboards(
+    mut actor_query: Query<(&mut Blackboard, &Health), With<BehaviorTree>>,
+) {
+    for (mut blackboard, health) in actor_query.iter_mut() {
+        blackboard.set_float(keys::HEALTH, health.current);
+        blackboard.set_float(keys::MAX_HEALTH, health.max);
+    }
+}
+
+/// Behavior trees don't get to touch `Path`, they leave where they want to go on the blackboard
+#[allow(clippy::type_complexity)]
+fn follow_behavior_trees(
+    mut actor_query: Query<(&Blackboard, &Transform, &mut Path), (With<BehaviorTree>, Without<ranger_ai::Stunned>)>,
+) {
+    for (blackboard, transform, mut path) in actor_query.iter_mut() {
+        match blackboard.vector(keys::MOVE_TO) {
+            Some(destination) => path.steering(&transform.translation, &destination),
+            None => path.movement = Vec3::ZERO,
+        }
+    }
+}
+
 /// The original RANGER didn't have any nifty camera scrolling. So the same has to apply here.
 /// As a result, we can't have any actors going out of bounds.
 ///
@@ -67,11 +165,33 @@ impl Plugin for ActorPlugin {
             .add_plugins((
                 player::PlayerPlugin,
                 bullet::BulletPlugin,
+                damage::DamagePlugin,
+                explosion::ExplosionPlugin,
+                status::StatusPlugin,
+                weapon::WeaponPlugin,
                 basic_enemy::EnemyPlugin,
+                ranged_enemy::RangedEnemyPlugin,
+                archetype::ArchetypePlugin,
+                pickup::PickupPlugin,
+                director::DirectorPlugin,
             ))
             .add_systems(Update, (
-                move_actors,
+                detect_targets
+                    .after(ranger_ai::threat::update_threat_tables)
+                    .before(ranger_ai::squad::share_perception)
+                    .before(ranger_ai::state_machine::update_state_machines),
+                update_tree_blackboards
+                    .after(damage::apply_damage)
+                    .before(ranger_ai::behavior_tree::tick_behavior_trees),
+                follow_behavior_trees
+                    .after(ranger_ai::behavior_tree::tick_behavior_trees)
+                    .before(ranger_ai::avoidance::avoid_neighbours),
+                move_actors.after(ranger_ai::avoidance::avoid_neighbours),
                 confine_to_screen,
             ));
+
+        if crate::DEBUG {
+            app.add_plugins(debug::AiDebugPlugin);
+        }
     }
 }
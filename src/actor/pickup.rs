@@ -0,0 +1,69 @@
+use bevy::prelude::*;
+use ranger_physics::AABB;
+
+const PICKUP_SIZE: Vec2 = Vec2::new(20.0, 20.0);
+
+/// Sent when `collector` walks over a pickup, for whoever cares about that item
+#[derive(Event, Debug, Clone)]
+pub struct PickupEvent {
+    pub collector: Entity,
+    pub item: String,
+}
+
+/// Something lying around for the player to walk over
+#[derive(Component, Debug)]
+pub struct Pickup {
+    pub item: String,
+}
+
+pub fn spawn_pickup(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    item: &str,
+    position: Vec3,
+) {
+    commands.spawn((
+        Pickup { item: item.to_string() },
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::YELLOW,
+                custom_size: Some(PICKUP_SIZE),
+                ..default()
+            },
+            texture: asset_server.load("sprites/placeholder.png"),
+            transform: Transform::from_translation(position.truncate().extend(-0.5)),
+            ..default()
+        },
+    ));
+}
+
+pub fn collect_pickups(
+    pickup_query: Query<(Entity, &Pickup, &Transform)>,
+    player_query: Query<(Entity, &AABB), With<super::player::Player>>,
+    mut commands: Commands,
+    mut pickup_events: EventWriter<PickupEvent>,
+) {
+    let Ok((player, player_aabb)) = player_query.get_single() else {
+        return;
+    };
+
+    for (entity, pickup, transform) in pickup_query.iter() {
+        let pickup_aabb = AABB::new(transform.translation, PICKUP_SIZE);
+        if player_aabb.static_static(&pickup_aabb).is_none() {
+            continue;
+        }
+
+        pickup_events.send(PickupEvent { collector: player, item: pickup.item.clone() });
+        commands.entity(entity).despawn();
+    }
+}
+
+pub struct PickupPlugin;
+
+impl Plugin for PickupPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_event::<PickupEvent>()
+            .add_systems(Update, collect_pickups);
+    }
+}
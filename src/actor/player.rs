@@ -6,6 +6,9 @@ pub struct Player;
 
 
 const PLAYER_SIZE: Vec2 = Vec2::new(50.0, 50.0);
+const PLAYER_SPEED: f32 = 200.0;
+// seconds the player can't be hurt after getting hit
+const PLAYER_INVULNERABILITY: f32 = 1.0;
 
 fn spawn_player(
     mut commands: Commands,
@@ -14,8 +17,13 @@ fn spawn_player(
     commands.spawn((
         Player,
         AABB::new(Vec3::ZERO, PLAYER_SIZE),
-        crate::actor::Health(100.0),
-        Path::new(200.0),
+        crate::actor::Health::new(100.0),
+        Path::new(PLAYER_SPEED),
+        crate::actor::BaseSpeed(PLAYER_SPEED),
+        ranger_ai::ThreatSource::default(),
+        crate::actor::Faction::Player,
+        crate::actor::damage::Invulnerability::new(PLAYER_INVULNERABILITY),
+        crate::actor::weapon::Inventory::player(),
         SpriteBundle {
             sprite: Sprite {
                 custom_size: Some(PLAYER_SIZE),
@@ -28,7 +36,7 @@ fn spawn_player(
 }
 
 fn move_player(
-    mut player_query: Query<&mut Path, With<Player>>,
+    mut player_query: Query<&mut Path, (With<Player>, Without<ranger_ai::Stunned>)>,
     res_keyboard_input: Res<ButtonInput<KeyCode>>,
 ) {
     if let Err(_) = player_query.get_single() {
@@ -48,7 +56,7 @@ fn move_player(
 }
 
 fn rotate_player_to_cursor(
-    mut player_query: Query<&mut Transform, With<Player>>,
+    mut player_query: Query<&mut Transform, (With<Player>, Without<ranger_ai::Stunned>)>,
     res_cursor_position: Res<crate::interface::CursorCoordinates>,
 ) {
     if let Err(_) = player_query.get_single() {
@@ -64,6 +72,15 @@ fn rotate_player_to_cursor(
     player_transform.rotation = Quat::from_rotation_z(angle);
 }
 
+fn end_game(
+    mut death_events: EventReader<crate::actor::damage::DeathEvent>,
+    player_query: Query<(), With<Player>>,
+    mut next_state: ResMut<NextState<crate::common::GameState>>,
+) {
+    if death_events.read().any(|death| player_query.contains(death.entity)) {
+        next_state.set(crate::common::GameState::GameOver);
+    }
+}
 
 pub struct PlayerPlugin;
 
@@ -71,6 +88,9 @@ impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
         app
             .add_systems(Startup, spawn_player)
-            .add_systems(Update, (move_player, rotate_player_to_cursor));
+            .add_systems(Update, (
+                (move_player, rotate_player_to_cursor).run_if(in_state(crate::common::GameState::Playing)),
+                end_game.after(crate::actor::damage::apply_damage),
+            ));
     }
 }
@@ -0,0 +1,230 @@
+use bevy::prelude::*;
+use ranger_physics::{AABB, Path};
+use ranger_ai::{blackboard::keys, AiState, Blackboard, NavPath, StateContext, StateMachine, Target};
+use ranger_ai::targeting::lead_target;
+use crate::world::navigation::{self, NavMeshes};
+use super::basic_enemy::{gave_up, is_dead, lost_target, sees_target};
+use super::archetype::EnemyWeapon;
+use super::bullet::{self, Bullet, Flight};
+
+#[derive(Component)]
+pub struct RangedEnemy;
+
+/// Seconds until the enemy can fire again
+#[derive(Component)]
+struct FireCooldown(f32);
+
+// ranged enemies that are free to act, i.e. not stunned
+type Acting = (With<RangedEnemy>, Without<ranger_ai::Stunned>);
+
+// starts shooting once this close
+const RANGED_ENEMY_FIRE_RANGE: f32 = 320.0;
+// and tries to hang around this far away while doing so
+const RANGED_ENEMY_PREFERRED_DISTANCE: f32 = 250.0;
+const RANGED_ENEMY_DISTANCE_TOLERANCE: f32 = 30.0;
+
+fn in_fire_range(context: &StateContext) -> bool {
+    context.blackboard.float(keys::DISTANCE_TO_TARGET)
+        .is_some_and(|distance| distance <= RANGED_ENEMY_FIRE_RANGE)
+}
+
+// a bit further than the fire range, so it doesn't flicker between the two at the edge
+fn out_of_fire_range(context: &StateContext) -> bool {
+    context.blackboard.float(keys::DISTANCE_TO_TARGET)
+        .is_some_and(|distance| distance > RANGED_ENEMY_FIRE_RANGE + RANGED_ENEMY_DISTANCE_TOLERANCE)
+}
+
+fn state_machine() -> StateMachine {
+    StateMachine::new(AiState::Idle)
+        .any_transition(AiState::Dead, is_dead)
+        .transition(AiState::Idle, AiState::Chase, sees_target)
+        .transition(AiState::Search, AiState::Chase, sees_target)
+        .transition(AiState::Chase, AiState::Attack, in_fire_range)
+        .transition(AiState::Attack, AiState::Chase, out_of_fire_range)
+        .transition(AiState::Chase, AiState::Search, lost_target)
+        .transition(AiState::Attack, AiState::Search, lost_target)
+        .transition(AiState::Chase, AiState::Idle, gave_up)
+        .transition(AiState::Attack, AiState::Idle, gave_up)
+        .transition(AiState::Search, AiState::Idle, gave_up)
+        .on_enter(AiState::Dead, |entity| { entity.remove::<Path>(); })
+}
+
+/// What makes an enemy with `Behaviour::Ranged` a ranged enemy. It needs a weapon to shoot.
+pub(super) fn behaviour() -> impl Bundle {
+    (
+        RangedEnemy,
+        FireCooldown(0.0),
+        state_machine(),
+    )
+}
+
+fn update_blackboard(
+    mut enemy_query: Query<(&mut Blackboard, &super::Health, &Target, &Transform), With<RangedEnemy>>,
+) {
+    for (mut blackboard, health, target, transform) in enemy_query.iter_mut() {
+        blackboard.set_float(keys::HEALTH, health.current);
+        blackboard.set_float(keys::MAX_HEALTH, health.max);
+
+        match target.point {
+            Some(point) => blackboard.set_float(keys::DISTANCE_TO_TARGET, transform.translation.distance(point)),
+            None => blackboard.remove(keys::DISTANCE_TO_TARGET),
+        }
+    }
+}
+
+fn face_target(
+    mut enemy_query: Query<(&Target, &StateMachine, &mut Transform), Acting>,
+    res_time: Res<Time>,
+) {
+    for (enemies_target, machine, mut enemy_transform) in enemy_query.iter_mut() {
+        if machine.is(AiState::Idle) || machine.is(AiState::Dead) {
+            continue;
+        }
+
+        let Some(destination) = enemies_target.destination(
+            enemy_transform.translation,
+            res_time.elapsed_seconds(),
+        ) else {
+            continue;
+        };
+
+        let angle = crate::common::get_angle(enemy_transform.translation, destination);
+        enemy_transform.rotation = Quat::from_rotation_z(angle);
+    }
+}
+
+/// Closes in while the target is out of range, and looks for it after losing it
+fn approach(
+    mut enemy_query: Query<(&Target, &StateMachine, &Transform, &AABB, &mut Path, &mut NavPath), Acting>,
+    mut res_nav_meshes: ResMut<NavMeshes>,
+    res_time: Res<Time>,
+) {
+    for (enemies_target, machine, transform, aabb, mut path, mut nav_path) in enemy_query.iter_mut() {
+        if !machine.is(AiState::Chase) && !machine.is(AiState::Search) {
+            continue;
+        }
+
+        let Some(destination) = enemies_target.destination(
+            transform.translation,
+            res_time.elapsed_seconds(),
+        ) else {
+            path.movement = Vec3::ZERO;
+            continue;
+        };
+
+        navigation::steer_towards(
+            &mut res_nav_meshes,
+            aabb,
+            &mut nav_path,
+            &mut path,
+            transform.translation,
+            destination,
+        );
+    }
+}
+
+/// Backs off or closes in until it's at its preferred distance from the target
+fn keep_distance(
+    mut enemy_query: Query<(&Target, &StateMachine, &Transform, &AABB, &mut Path, &mut NavPath), Acting>,
+    mut res_nav_meshes: ResMut<NavMeshes>,
+) {
+    for (enemies_target, machine, transform, aabb, mut path, mut nav_path) in enemy_query.iter_mut() {
+        if !machine.is(AiState::Attack) {
+            continue;
+        }
+
+        let Some(point) = enemies_target.point else {
+            continue;
+        };
+
+        let offset = transform.translation - point;
+        if (offset.length() - RANGED_ENEMY_PREFERRED_DISTANCE).abs() <= RANGED_ENEMY_DISTANCE_TOLERANCE {
+            path.movement = Vec3::ZERO;
+            continue;
+        }
+
+        let spot = point + offset.normalize_or_zero() * RANGED_ENEMY_PREFERRED_DISTANCE;
+        navigation::steer_towards(
+            &mut res_nav_meshes,
+            aabb,
+            &mut nav_path,
+            &mut path,
+            transform.translation,
+            spot,
+        );
+    }
+}
+
+/// Fires at where the target is going to be, going by how it's moving right now
+fn shoot(
+    mut enemy_query: Query<(Entity, &Target, &StateMachine, &Transform, &EnemyWeapon, &mut FireCooldown), Acting>,
+    target_query: Query<&Path>,
+    mut bullets: bullet::Bullets,
+    res_time: Res<Time>,
+) {
+    for (entity, enemies_target, machine, transform, weapon, mut cooldown) in enemy_query.iter_mut() {
+        cooldown.0 -= res_time.delta_seconds();
+
+        let Some(point) = enemies_target.point else {
+            continue;
+        };
+
+        if !machine.is(AiState::Attack) || cooldown.0 > 0.0 {
+            continue;
+        }
+
+        let target_velocity = enemies_target.entity
+            .and_then(|target| target_query.get(target).ok())
+            .map_or(Vec3::ZERO, |path| path.movement);
+        let aim = lead_target(transform.translation, point, target_velocity, weapon.bullet_speed);
+
+        bullets.fire(
+            *transform,
+            aim,
+            Flight::new(weapon.bullet_speed, weapon.ballistics),
+            Bullet { owner: entity, faction: super::Faction::Enemy, damage: weapon.damage, effect: weapon.effect },
+            bullet::DEFAULT_BULLET_SPRITE,
+            bullet::DEFAULT_BULLET_SIZE,
+        );
+
+        cooldown.0 = weapon.fire_interval;
+    }
+}
+
+fn idle(
+    mut enemy_query: Query<(&StateMachine, &mut Path), Acting>,
+) {
+    for (machine, mut path) in enemy_query.iter_mut() {
+        if !machine.is(AiState::Idle) {
+            continue;
+        }
+
+        path.movement = Vec3::ZERO;
+    }
+}
+
+
+pub struct RangedEnemyPlugin;
+
+impl Plugin for RangedEnemyPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(Update, (
+                update_blackboard
+                    .after(super::detect_targets)
+                    .after(super::damage::apply_damage)
+                    .before(ranger_ai::state_machine::update_state_machines),
+                face_target.after(ranger_ai::state_machine::update_state_machines),
+                approach
+                    .after(ranger_ai::state_machine::update_state_machines)
+                    .before(ranger_ai::avoidance::avoid_neighbours),
+                keep_distance
+                    .after(ranger_ai::state_machine::update_state_machines)
+                    .before(ranger_ai::avoidance::avoid_neighbours),
+                idle
+                    .after(ranger_ai::state_machine::update_state_machines)
+                    .before(ranger_ai::avoidance::avoid_neighbours),
+                shoot.after(face_target),
+            ));
+    }
+}
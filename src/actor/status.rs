@@ -0,0 +1,274 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+use ranger_physics::Path;
+use ranger_ai::Stunned;
+use super::damage::{DamageEvent, DamageKind};
+
+// poison is the only thing that stacks, up to this many times
+const MAX_POISON_STACKS: u32 = 5;
+// seconds between two ticks of damage over time
+const TICK_INTERVAL: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum StatusKind {
+    /// Fire damage over time. A new burn replaces the old one if it's hotter, and restarts it.
+    Burn,
+    /// Multiplies the speed by `strength`. The strongest slow wins, and restarts it.
+    Slow,
+    /// No thinking, moving or shooting. Lasts as long as the longest stun.
+    Stun,
+    /// Poison damage over time that stacks, every stack restarting all of them.
+    Poison,
+}
+
+impl StatusKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            StatusKind::Burn => "burning",
+            StatusKind::Slow => "slowed",
+            StatusKind::Stun => "stunned",
+            StatusKind::Poison => "poisoned",
+        }
+    }
+}
+
+/// What a weapon or hazard does to whatever it hits
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "AuthoredStatusEffect")]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    /// Seconds
+    pub duration: f32,
+    /// Damage per second for burn and poison, the speed multiplier for slow
+    pub strength: f32,
+}
+
+/// A status effect the way it's written in RON. Only stuns get to leave out the strength, a slow
+/// without one would stop whatever it hits dead in its tracks.
+#[derive(Deserialize)]
+struct AuthoredStatusEffect {
+    kind: StatusKind,
+    duration: f32,
+    // written as a plain number, not as an option
+    #[serde(default, deserialize_with = "present")]
+    strength: Option<f32>,
+}
+
+fn present<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<f32>, D::Error> {
+    f32::deserialize(deserializer).map(Some)
+}
+
+impl TryFrom<AuthoredStatusEffect> for StatusEffect {
+    type Error = String;
+
+    fn try_from(authored: AuthoredStatusEffect) -> Result<Self, Self::Error> {
+        let strength = match (authored.kind, authored.strength) {
+            (_, Some(strength)) => strength,
+            (StatusKind::Stun, None) => 0.0,
+            (kind, None) => return Err(format!("{kind:?} effects need a strength")),
+        };
+
+        Ok(Self { kind: authored.kind, duration: authored.duration, strength })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActiveEffect {
+    pub effect: StatusEffect,
+    pub remaining: f32,
+    pub stacks: u32,
+    /// Who to blame for the damage it does
+    pub source: Entity,
+}
+
+/// Everything currently affecting an actor, at most one entry per kind
+#[derive(Component, Debug, Default)]
+pub struct StatusEffects {
+    pub effects: Vec<ActiveEffect>,
+    until_tick: f32,
+}
+
+impl StatusEffects {
+    pub fn get(&self, kind: StatusKind) -> Option<&ActiveEffect> {
+        self.effects.iter().find(|active| active.effect.kind == kind)
+    }
+
+    pub fn apply(&mut self, effect: StatusEffect, source: Entity) {
+        let Some(active) = self.effects.iter_mut().find(|active| active.effect.kind == effect.kind) else {
+            self.effects.push(ActiveEffect { effect, remaining: effect.duration, stacks: 1, source });
+            return;
+        };
+
+        let stronger = match effect.kind {
+            StatusKind::Burn => effect.strength >= active.effect.strength,
+            // a lower multiplier is slower
+            StatusKind::Slow => effect.strength <= active.effect.strength,
+            StatusKind::Stun => effect.duration >= active.remaining,
+            StatusKind::Poison => {
+                active.stacks = (active.stacks + 1).min(MAX_POISON_STACKS);
+                true
+            },
+        };
+
+        if stronger {
+            active.effect.strength = effect.strength;
+            active.effect.duration = effect.duration;
+            active.remaining = effect.duration;
+            active.source = source;
+        }
+    }
+
+    /// 1 unless slowed
+    pub fn speed_multiplier(&self) -> f32 {
+        self.get(StatusKind::Slow).map_or(1.0, |slow| slow.effect.strength)
+    }
+
+    pub fn is_stunned(&self) -> bool {
+        self.get(StatusKind::Stun).is_some()
+    }
+}
+
+/// Puts a status effect on `target`
+#[derive(Event, Debug, Clone, Copy)]
+pub struct StatusEvent {
+    pub target: Entity,
+    pub source: Entity,
+    pub effect: StatusEffect,
+}
+
+fn apply_status_effects(
+    mut status_events: EventReader<StatusEvent>,
+    mut actor_query: Query<Option<&mut StatusEffects>, With<super::Health>>,
+    mut commands: Commands,
+) {
+    for status in status_events.read() {
+        let Ok(effects) = actor_query.get_mut(status.target) else {
+            continue;
+        };
+
+        match effects {
+            Some(mut effects) => effects.apply(status.effect, status.source),
+            None => {
+                let mut effects = StatusEffects::default();
+                effects.apply(status.effect, status.source);
+                commands.entity(status.target).insert(effects);
+            },
+        }
+    }
+}
+
+/// Counts the effects down, and deals their damage every tick
+fn tick_status_effects(
+    mut actor_query: Query<(Entity, &mut StatusEffects)>,
+    mut damage_events: EventWriter<DamageEvent>,
+    res_time: Res<Time>,
+) {
+    let delta = res_time.delta_seconds();
+
+    for (entity, mut effects) in actor_query.iter_mut() {
+        effects.until_tick -= delta;
+        let ticks = effects.until_tick <= 0.0;
+        if ticks {
+            effects.until_tick += TICK_INTERVAL;
+        }
+
+        for active in effects.effects.iter_mut() {
+            active.remaining -= delta;
+        }
+
+        // whatever ran out doesn't get to tick anymore
+        effects.effects.retain(|active| active.remaining > 0.0);
+
+        if !ticks {
+            continue;
+        }
+
+        for active in effects.effects.iter() {
+            let kind = match active.effect.kind {
+                StatusKind::Burn => DamageKind::Fire,
+                StatusKind::Poison => DamageKind::Poison,
+                StatusKind::Slow | StatusKind::Stun => continue,
+            };
+
+            damage_events.send(DamageEvent {
+                target: entity,
+                source: active.source,
+                amount: active.effect.strength * active.stacks as f32 * TICK_INTERVAL,
+                kind,
+                periodic: true,
+            });
+        }
+    }
+}
+
+/// Slows down and stuns whatever the effects say, and lets go of it again afterwards
+pub fn apply_status_modifiers(
+    mut actor_query: Query<(Entity, &StatusEffects, &super::BaseSpeed, &mut Path, Has<Stunned>)>,
+    mut commands: Commands,
+) {
+    for (entity, effects, base_speed, mut path, stunned) in actor_query.iter_mut() {
+        path.velocity = base_speed.0 * effects.speed_multiplier();
+
+        match (effects.is_stunned(), stunned) {
+            (true, false) => {
+                // nothing steers stunned actors, so whatever they were doing stops here
+                path.movement = Vec3::ZERO;
+                commands.entity(entity).insert(Stunned);
+            },
+            (false, true) => { commands.entity(entity).remove::<Stunned>(); },
+            _ => (),
+        }
+    }
+}
+
+pub struct StatusPlugin;
+
+impl Plugin for StatusPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_event::<StatusEvent>()
+            .add_systems(Update, (
+                apply_status_effects.after(super::bullet::check_for_collisions),
+                tick_status_effects
+                    .after(apply_status_effects)
+                    .before(super::damage::apply_damage),
+                apply_status_modifiers
+                    .after(tick_status_effects)
+                    .before(ranger_ai::state_machine::update_state_machines),
+            ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn effects_stack_and_refresh_by_kind() {
+        let source = Entity::PLACEHOLDER;
+        let mut effects = StatusEffects::default();
+        let poison = StatusEffect { kind: StatusKind::Poison, duration: 3.0, strength: 4.0 };
+        let slow = StatusEffect { kind: StatusKind::Slow, duration: 2.0, strength: 0.5 };
+
+        for _ in 0..10 {
+            effects.apply(poison, source);
+        }
+        effects.apply(slow, source);
+        // weaker, so it's ignored
+        effects.apply(StatusEffect { strength: 0.8, ..slow }, source);
+
+        assert_eq!(effects.effects.len(), 2);
+        assert_eq!(effects.get(StatusKind::Poison).unwrap().stacks, MAX_POISON_STACKS);
+        assert_eq!(effects.speed_multiplier(), 0.5);
+        assert!(!effects.is_stunned());
+    }
+
+    #[test]
+    fn only_stuns_go_without_a_strength() {
+        let stun: StatusEffect = ron::from_str("(kind: Stun, duration: 1.0)").unwrap();
+        assert_eq!(stun.strength, 0.0);
+
+        assert!(ron::from_str::<StatusEffect>("(kind: Slow, duration: 1.0)").is_err());
+        assert!(ron::from_str::<StatusEffect>("(kind: Burn, duration: 1.0, strength: 5.0)").is_ok());
+    }
+}
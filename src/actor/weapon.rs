@@ -0,0 +1,434 @@
+use bevy::asset::LoadedFolder;
+use bevy::input::mouse::MouseWheel;
+use bevy::prelude::*;
+use serde::Deserialize;
+use super::bullet::{self, Ballistics, Bullet, Flight, ProjectileModifiers};
+use super::status::StatusEffect;
+
+// what the player starts out with, in number key order
+const PLAYER_LOADOUT: [&str; 4] = ["pistol", "shotgun", "rifle", "launcher"];
+const WEAPON_KEYS: [KeyCode; 9] = [
+    KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3,
+    KeyCode::Digit4, KeyCode::Digit5, KeyCode::Digit6,
+    KeyCode::Digit7, KeyCode::Digit8, KeyCode::Digit9,
+];
+
+fn default_pellets() -> u32 {
+    1
+}
+
+fn default_projectile_size() -> f32 {
+    bullet::DEFAULT_BULLET_SIZE
+}
+
+fn default_sprite() -> String {
+    bullet::DEFAULT_BULLET_SPRITE.to_string()
+}
+
+/// A player weapon, loaded from `assets/weapons/*.weapon.ron`
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct WeaponDefinition {
+    pub name: String,
+    /// Shots per second
+    pub fire_rate: f32,
+    /// Keeps firing while the button is held, instead of once per click
+    #[serde(default)]
+    pub automatic: bool,
+    pub projectile_speed: f32,
+    #[serde(default)]
+    pub ballistics: Ballistics,
+    /// Per pellet
+    pub damage: f32,
+    /// Width of the cone the pellets scatter in, in degrees
+    #[serde(default)]
+    pub spread: f32,
+    #[serde(default = "default_pellets")]
+    pub pellets: u32,
+    #[serde(default = "default_sprite")]
+    pub sprite: String,
+    #[serde(default = "default_projectile_size")]
+    pub projectile_size: f32,
+    #[serde(default)]
+    pub effect: Option<StatusEffect>,
+    #[serde(default)]
+    pub modifiers: ProjectileModifiers,
+    /// Shots per magazine
+    pub magazine: u32,
+    /// The most spare rounds the player can carry
+    pub max_reserve: u32,
+    /// Seconds
+    pub reload_time: f32,
+}
+
+impl WeaponDefinition {
+    /// Where each pellet of one shot from `origin` at `destination` goes
+    pub fn pellet_destinations(&self, origin: Vec3, destination: Vec3) -> Vec<Vec3> {
+        let offset = destination - origin;
+        let spread = self.spread.to_radians();
+
+        (0..self.pellets)
+            .map(|_| {
+                let angle = (fastrand::f32() - 0.5) * spread;
+                origin + Quat::from_rotation_z(angle) * offset
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ammo {
+    pub magazine: u32,
+    pub reserve: u32,
+}
+
+impl Ammo {
+    pub fn full(weapon: &WeaponDefinition) -> Self {
+        Self { magazine: weapon.magazine, reserve: weapon.max_reserve }
+    }
+
+    /// Tops the magazine up from the reserve
+    pub fn reload(&mut self, weapon: &WeaponDefinition) {
+        let missing = weapon.magazine.saturating_sub(self.magazine).min(self.reserve);
+        self.magazine += missing;
+        self.reserve -= missing;
+    }
+
+    pub fn can_reload(&self, weapon: &WeaponDefinition) -> bool {
+        self.magazine < weapon.magazine && self.reserve > 0
+    }
+
+    pub fn refill(&mut self, amount: u32, weapon: &WeaponDefinition) {
+        self.reserve = (self.reserve + amount).min(weapon.max_reserve);
+    }
+}
+
+#[derive(Debug)]
+pub struct CarriedWeapon {
+    pub name: String,
+    /// None until the definition has loaded, then it starts out full
+    pub ammo: Option<Ammo>,
+    /// Seconds until it can fire again. Goes below 0 by whatever's left over of the frame it
+    /// became ready in, so the next shot comes that much sooner.
+    cooldown: f32,
+}
+
+impl CarriedWeapon {
+    pub fn new(name: String) -> Self {
+        Self { name, ammo: None, cooldown: 0.0 }
+    }
+
+    fn ammo(&mut self, weapon: &WeaponDefinition) -> &mut Ammo {
+        self.ammo.get_or_insert_with(|| Ammo::full(weapon))
+    }
+
+    pub fn cool_down(&mut self, delta: f32) {
+        // anything more than a frame left over is just the weapon lying around unused
+        self.cooldown = (self.cooldown - delta).max(-delta);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.cooldown <= 0.0
+    }
+
+    fn fired(&mut self, weapon: &WeaponDefinition) {
+        self.cooldown += 1.0 / weapon.fire_rate;
+    }
+}
+
+/// Keeps every weapon in the weapons folder loaded
+#[derive(Resource)]
+struct WeaponFolder(#[allow(dead_code)] Handle<LoadedFolder>);
+
+/// The weapons the player carries, by name
+#[derive(Component, Debug)]
+pub struct Inventory {
+    pub weapons: Vec<CarriedWeapon>,
+    pub current: usize,
+    /// Seconds until the current weapon is done reloading
+    reloading: Option<f32>,
+}
+
+impl Inventory {
+    pub fn new(weapons: Vec<String>) -> Self {
+        Self {
+            weapons: weapons.into_iter().map(CarriedWeapon::new).collect(),
+            current: 0,
+            reloading: None,
+        }
+    }
+
+    pub fn player() -> Self {
+        Self::new(PLAYER_LOADOUT.iter().map(|name| name.to_string()).collect())
+    }
+
+    pub fn current(&self) -> Option<&str> {
+        self.weapons.get(self.current).map(|weapon| weapon.name.as_str())
+    }
+
+    pub fn current_ammo(&self) -> Option<Ammo> {
+        self.weapons.get(self.current).and_then(|weapon| weapon.ammo)
+    }
+
+    pub fn is_reloading(&self) -> bool {
+        self.reloading.is_some()
+    }
+
+    /// Switching away cancels a reload
+    pub fn select(&mut self, index: usize) {
+        if index < self.weapons.len() && index != self.current {
+            self.current = index;
+            self.reloading = None;
+        }
+    }
+
+    /// Moves `steps` weapons further, wrapping around at either end
+    pub fn cycle(&mut self, steps: i32) {
+        if self.weapons.is_empty() {
+            return;
+        }
+
+        let count = self.weapons.len() as i32;
+        self.select((self.current as i32 + steps).rem_euclid(count) as usize);
+    }
+}
+
+pub fn find_weapon<'a>(weapons: &'a Assets<WeaponDefinition>, name: &str) -> Option<&'a WeaponDefinition> {
+    weapons.iter()
+        .find(|(_, weapon)| weapon.name == name)
+        .map(|(_, weapon)| weapon)
+}
+
+fn load_weapons(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    commands.insert_resource(WeaponFolder(asset_server.load_folder("weapons")));
+}
+
+fn switch_weapons(
+    mut player_query: Query<&mut Inventory, With<super::player::Player>>,
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    res_keyboard_input: Res<ButtonInput<KeyCode>>,
+) {
+    let Ok(mut inventory) = player_query.get_single_mut() else {
+        return;
+    };
+
+    if let Some(index) = WEAPON_KEYS.iter().position(|key| res_keyboard_input.just_pressed(*key)) {
+        inventory.select(index);
+    }
+
+    let scrolled: f32 = mouse_wheel_events.read().map(|event| event.y).sum();
+    if scrolled != 0.0 {
+        inventory.cycle(-scrolled.signum() as i32);
+    }
+}
+
+/// Every carried weapon cools down, whether it's in hand or not
+fn cool_down_weapons(
+    mut inventory_query: Query<&mut Inventory>,
+    res_time: Res<Time>,
+) {
+    for mut inventory in inventory_query.iter_mut() {
+        for weapon in inventory.weapons.iter_mut() {
+            weapon.cool_down(res_time.delta_seconds());
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn fire_weapons(
+    mut player_query: Query<(Entity, &Transform, &mut Inventory), (With<super::player::Player>, Without<ranger_ai::Stunned>)>,
+    mut bullets: bullet::Bullets,
+    res_mouse_input: Res<ButtonInput<MouseButton>>,
+    res_cursor_coordinates: Res<crate::interface::CursorCoordinates>,
+    res_weapons: Res<Assets<WeaponDefinition>>,
+) {
+    let Ok((player, transform, mut inventory)) = player_query.get_single_mut() else {
+        return;
+    };
+
+    if !inventory.weapons.get(inventory.current).is_some_and(CarriedWeapon::is_ready) {
+        return;
+    }
+
+    let Some(weapon) = inventory.current().and_then(|name| find_weapon(&res_weapons, name)) else {
+        return;
+    };
+
+    let pulled = match weapon.automatic {
+        true => res_mouse_input.pressed(MouseButton::Left),
+        false => res_mouse_input.just_pressed(MouseButton::Left),
+    };
+    if !pulled {
+        return;
+    }
+
+    let current = inventory.current;
+    let ammo = inventory.weapons[current].ammo(weapon);
+    if ammo.magazine == 0 {
+        return;
+    }
+    ammo.magazine -= 1;
+
+    // pulling the trigger with rounds left in the magazine cancels the reload
+    inventory.reloading = None;
+
+    for destination in weapon.pellet_destinations(transform.translation, res_cursor_coordinates.0) {
+        let mut bullet = bullets.fire(
+            *transform,
+            destination,
+            Flight::new(weapon.projectile_speed, weapon.ballistics),
+            Bullet { owner: player, faction: super::Faction::Player, damage: weapon.damage, effect: weapon.effect },
+            &weapon.sprite,
+            weapon.projectile_size,
+        );
+        weapon.modifiers.apply(&mut bullet);
+    }
+
+    inventory.weapons[current].fired(weapon);
+}
+
+/// Reloads on R, or on its own once the magazine runs dry
+fn reload_weapons(
+    mut player_query: Query<&mut Inventory, (With<super::player::Player>, Without<ranger_ai::Stunned>)>,
+    res_keyboard_input: Res<ButtonInput<KeyCode>>,
+    res_weapons: Res<Assets<WeaponDefinition>>,
+    res_time: Res<Time>,
+) {
+    let Ok(mut inventory) = player_query.get_single_mut() else {
+        return;
+    };
+
+    let Some(weapon) = inventory.current().and_then(|name| find_weapon(&res_weapons, name)) else {
+        return;
+    };
+
+    let current = inventory.current;
+    match inventory.reloading {
+        Some(remaining) if remaining > res_time.delta_seconds() => {
+            inventory.reloading = Some(remaining - res_time.delta_seconds());
+        },
+        Some(_) => {
+            inventory.weapons[current].ammo(weapon).reload(weapon);
+            inventory.reloading = None;
+        },
+        None => {
+            let ammo = *inventory.weapons[current].ammo(weapon);
+            let wants_to = res_keyboard_input.just_pressed(KeyCode::KeyR) || ammo.magazine == 0;
+
+            if wants_to && ammo.can_reload(weapon) {
+                inventory.reloading = Some(weapon.reload_time);
+            }
+        },
+    }
+}
+
+/// Every ammo pickup is worth a magazine for every weapon
+fn collect_ammo(
+    mut pickup_events: EventReader<super::pickup::PickupEvent>,
+    mut inventory_query: Query<&mut Inventory>,
+    res_weapons: Res<Assets<WeaponDefinition>>,
+) {
+    for pickup in pickup_events.read() {
+        if pickup.item != "ammo" {
+            continue;
+        }
+
+        let Ok(mut inventory) = inventory_query.get_mut(pickup.collector) else {
+            continue;
+        };
+
+        for carried in inventory.weapons.iter_mut() {
+            if let Some(weapon) = find_weapon(&res_weapons, &carried.name) {
+                carried.ammo(weapon).refill(weapon.magazine, weapon);
+            }
+        }
+    }
+}
+
+pub struct WeaponPlugin;
+
+impl Plugin for WeaponPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_asset::<WeaponDefinition>()
+            .register_asset_loader(ranger_ai::RonLoader::<WeaponDefinition>::new(&["weapon.ron"]))
+            .add_systems(Startup, load_weapons)
+            .add_systems(Update, (
+                switch_weapons,
+                reload_weapons
+                    .after(switch_weapons)
+                    .run_if(in_state(crate::common::GameState::Playing)),
+                collect_ammo.after(super::pickup::collect_pickups),
+                cool_down_weapons,
+                fire_weapons
+                    .after(cool_down_weapons)
+                    .after(reload_weapons)
+                    .before(bullet::check_for_collisions)
+                    .run_if(in_state(crate::common::GameState::Playing)),
+            ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weapons_parse_and_cycle() {
+        let mut names = vec![];
+        for file in [
+            include_str!("../../assets/weapons/pistol.weapon.ron"),
+            include_str!("../../assets/weapons/shotgun.weapon.ron"),
+            include_str!("../../assets/weapons/rifle.weapon.ron"),
+            include_str!("../../assets/weapons/launcher.weapon.ron"),
+        ] {
+            let weapon: WeaponDefinition = ron::from_str(file).unwrap();
+            assert_eq!(weapon.pellet_destinations(Vec3::ZERO, Vec3::X * 100.0).len(), weapon.pellets as usize);
+            names.push(weapon.name);
+        }
+        assert_eq!(names, PLAYER_LOADOUT);
+
+        let mut inventory = Inventory::player();
+        inventory.cycle(-1);
+        assert_eq!(inventory.current(), Some("launcher"));
+        inventory.cycle(3);
+        assert_eq!(inventory.current(), Some("rifle"));
+    }
+
+    #[test]
+    fn reloads_come_out_of_the_reserve() {
+        let weapon: WeaponDefinition = ron::from_str(include_str!("../../assets/weapons/pistol.weapon.ron")).unwrap();
+        let mut ammo = Ammo { magazine: 1, reserve: 3 };
+
+        assert!(ammo.can_reload(&weapon));
+        ammo.reload(&weapon);
+        assert_eq!(ammo, Ammo { magazine: 4, reserve: 0 });
+        assert!(!ammo.can_reload(&weapon));
+
+        ammo.refill(weapon.max_reserve * 2, &weapon);
+        assert_eq!(ammo.reserve, weapon.max_reserve);
+    }
+
+    #[test]
+    fn fire_rate_ignores_the_frame_rate() {
+        let weapon: WeaponDefinition = ron::from_str(include_str!("../../assets/weapons/rifle.weapon.ron")).unwrap();
+
+        for frames in [30, 60, 144] {
+            let mut carried = CarriedWeapon::new(weapon.name.clone());
+            let mut shots = 0;
+
+            // one second, holding the trigger from the start
+            for _ in 0..frames {
+                if carried.is_ready() {
+                    carried.fired(&weapon);
+                    shots += 1;
+                }
+                carried.cool_down(1.0 / frames as f32);
+            }
+
+            assert_eq!(shots, weapon.fire_rate as u32);
+        }
+    }
+}
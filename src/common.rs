@@ -19,5 +19,12 @@ pub fn get_angle(origin: Vec3, destination: Vec3) -> f32 {
     y.atan2(x)
 }
 
+#[derive(States, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum GameState {
+    #[default]
+    Playing,
+    GameOver,
+}
+
 #[derive(Resource)]
-pub struct DebugTimer(pub Timer);
+pub struct DebugTimer(#[allow(dead_code)] pub Timer);
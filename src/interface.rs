@@ -3,6 +3,9 @@ use bevy::prelude::*;
 #[derive(Resource, Default)]
 pub struct CursorCoordinates(pub Vec3);
 
+#[derive(Component)]
+pub struct Hud;
+
 pub fn update_cursor_position(
     mut res_cursor_coordinates: ResMut<CursorCoordinates>,
     window_query: Query<&Window, With<bevy::window::PrimaryWindow>>,
@@ -26,3 +29,87 @@ pub fn update_cursor_position(
         res_cursor_coordinates.0 = world_coordinates.extend(0.0);
     }
 }
+
+pub fn spawn_hud(
+    mut commands: Commands,
+) {
+    commands.spawn((
+        Hud,
+        TextBundle::from_section("", TextStyle { font_size: 20.0, ..default() })
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(10.0),
+                left: Val::Px(10.0),
+                ..default()
+            }),
+    ));
+}
+
+/// Health, shield, weapon and whatever's affecting the player
+#[allow(clippy::type_complexity)]
+pub fn update_hud(
+    mut hud_query: Query<&mut Text, With<Hud>>,
+    player_query: Query<(
+        &crate::actor::Health,
+        Option<&crate::actor::damage::Shield>,
+        Option<&crate::actor::status::StatusEffects>,
+        Option<&crate::actor::weapon::Inventory>,
+    ), With<crate::actor::player::Player>>,
+) {
+    let (Ok(mut text), Ok((health, shield, effects, inventory))) = (hud_query.get_single_mut(), player_query.get_single()) else {
+        return;
+    };
+
+    let mut lines = vec![format!("health {:.0}/{:.0}", health.current, health.max)];
+
+    if let Some(shield) = shield {
+        lines.push(format!("shield {:.0}/{:.0}", shield.current, shield.stats.capacity));
+    }
+
+    if let Some(inventory) = inventory {
+        match (inventory.current(), inventory.current_ammo()) {
+            (Some(weapon), Some(ammo)) if inventory.is_reloading() => lines.push(format!("{weapon} {}/{} reloading", ammo.magazine, ammo.reserve)),
+            (Some(weapon), Some(ammo)) => lines.push(format!("{weapon} {}/{}", ammo.magazine, ammo.reserve)),
+            (Some(weapon), None) => lines.push(weapon.to_string()),
+            (None, _) => (),
+        }
+    }
+
+    for active in effects.iter().flat_map(|effects| effects.effects.iter()) {
+        match active.stacks {
+            1 => lines.push(format!("{} {:.1}s", active.effect.kind.label(), active.remaining)),
+            stacks => lines.push(format!("{} x{} {:.1}s", active.effect.kind.label(), stacks, active.remaining)),
+        }
+    }
+
+    text.sections[0].value = lines.join("\n");
+}
+
+/// Freezes everything and puts up the final score
+pub fn show_game_over(
+    mut commands: Commands,
+    res_score: Res<crate::actor::archetype::Score>,
+    mut res_time: ResMut<Time<Virtual>>,
+) {
+    res_time.pause();
+
+    commands.spawn(NodeBundle {
+        style: Style {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        ..default()
+    }).with_children(|parent| {
+        parent.spawn(TextBundle::from_section(
+            format!("GAME OVER\nscore: {}", res_score.0),
+            TextStyle {
+                font_size: 60.0,
+                color: Color::RED,
+                ..default()
+            },
+        ).with_text_justify(JustifyText::Center));
+    });
+}
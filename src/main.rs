@@ -29,12 +29,15 @@ fn main() {
             .build(),
         )
         .add_plugins((
+            ranger_ai::AiPlugin,
             actor::ActorPlugin,
             world::WorldPlugin,
         ))
+        .init_state::<common::GameState>()
         .insert_resource(common::DebugTimer(Timer::from_seconds(1.5, TimerMode::Repeating)))
         .insert_resource(interface::CursorCoordinates(Vec3::ZERO))
-        .add_systems(Startup, init)
-        .add_systems(Update, interface::update_cursor_position)
+        .add_systems(Startup, (init, interface::spawn_hud))
+        .add_systems(OnEnter(common::GameState::GameOver), interface::show_game_over)
+        .add_systems(Update, (interface::update_cursor_position, interface::update_hud))
         .run();
 }
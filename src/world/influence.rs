@@ -0,0 +1,62 @@
+use bevy::prelude::*;
+use ranger_ai::{InfluenceLayer, InfluenceMap, Target};
+
+/// Spreading influence every frame would make it spread faster on faster machines
+#[derive(Resource)]
+struct InfluenceTimer(Timer);
+
+fn rebuild_influence_map(
+    grid_query: Query<&super::map::Grid, Changed<super::map::Grid>>,
+    mut commands: Commands,
+) {
+    let Ok(grid) = grid_query.get_single() else {
+        return;
+    };
+
+    commands.insert_resource(InfluenceMap::new(grid.walkable_grid()));
+}
+
+/// Stamps the player, the AI actors and the bullets onto their layers, then spreads it all out
+fn update_influence_map(
+    player_query: Query<&Transform, With<crate::actor::player::Player>>,
+    ai_query: Query<&Transform, With<Target>>,
+    bullet_query: Query<&Transform, With<crate::actor::bullet::Bullet>>,
+    res_influence_map: Option<ResMut<InfluenceMap>>,
+    mut res_influence_timer: ResMut<InfluenceTimer>,
+    res_time: Res<Time>,
+) {
+    let Some(mut influence_map) = res_influence_map else {
+        return;
+    };
+
+    if !res_influence_timer.0.tick(res_time.delta()).just_finished() {
+        return;
+    }
+
+    for transform in player_query.iter() {
+        influence_map.stamp(InfluenceLayer::PlayerThreat, transform.translation, 1.0);
+    }
+
+    for transform in ai_query.iter() {
+        influence_map.stamp(InfluenceLayer::AllyDensity, transform.translation, 1.0);
+    }
+
+    for transform in bullet_query.iter() {
+        influence_map.stamp(InfluenceLayer::BulletTraffic, transform.translation, 1.0);
+    }
+
+    influence_map.update(res_influence_timer.0.duration().as_secs_f32());
+}
+
+pub struct InfluencePlugin;
+
+impl Plugin for InfluencePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(InfluenceTimer(Timer::from_seconds(0.1, TimerMode::Repeating)))
+            .add_systems(Update, (
+                rebuild_influence_map,
+                update_influence_map,
+            ));
+    }
+}
@@ -1,5 +1,8 @@
 use bevy::prelude::*;
+use bevy::utils::HashMap;
 use ranger_physics::AABB;
+use ranger_ai::{PatrolMode, PatrolRoute, WalkableGrid, Waypoint};
+use serde::Deserialize;
 
 const DEFAULT_FIELD_WIDTH: f32 = 75.0;
 const DEFAULT_FIELD_HEIGHT: f32 = 75.0;
@@ -18,6 +21,55 @@ impl Grid {
         &self.fields[index]
     }
 
+    fn field_mut(&mut self, row: usize, column: usize) -> &mut Field {
+        let index = ((row-1) * self.columns + column) - 1;
+
+        &mut self.fields[index]
+    }
+
+    pub fn set_solid(&mut self, row: usize, column: usize) {
+        self.field_mut(row, column).solid = true;
+    }
+
+    pub fn set_spawn(&mut self, row: usize, column: usize) {
+        self.field_mut(row, column).spawn = true;
+    }
+
+    /// Centres of the fields enemies can spawn on
+    pub fn spawn_points(&self) -> Vec<Vec3> {
+        self.fields.iter()
+            .filter(|field| field.spawn && !field.solid)
+            .map(|field| field.point)
+            .collect()
+    }
+
+    /// Whether the AABB overlaps any solid field. Outside the grid nothing is solid.
+    pub fn overlaps_solid(&self, bounding_box: &AABB) -> bool {
+        self.fields.iter()
+            .filter(|field| field.solid)
+            .any(|field| bounding_box.static_static(&field.as_aabb()).is_some())
+    }
+
+    /// Position and size of every solid field
+    pub fn solid_fields(&self) -> impl Iterator<Item = (Vec3, Vec2)> + '_ {
+        self.fields.iter()
+            .filter(|field| field.solid)
+            .map(|field| (field.point, Vec2::new(field.width, field.height)))
+    }
+
+    /// The grid as ranger_ai sees it, for building navmeshes
+    pub fn walkable_grid(&self) -> WalkableGrid {
+        let first = self.field(1, 1);
+
+        WalkableGrid {
+            rows: self.rows,
+            columns: self.columns,
+            cell_size: Vec2::new(first.width, first.height),
+            origin: Vec2::new(first.point.x - first.width / 2.0, first.point.y + first.height / 2.0),
+            walkable: self.fields.iter().map(|field| !field.solid).collect(),
+        }
+    }
+
     fn count(&self, row: &mut usize, column: &mut usize) {
         if *column < self.columns {
             *column += 1;
@@ -52,6 +104,102 @@ impl Grid {
         containing_fields
     }
 
+    /// Where a point is in fields, counting columns from the left and rows from the top edge of
+    /// the grid. All fields are the same size, so the field it's in is this rounded down.
+    fn grid_space(&self, point: Vec3) -> Vec2 {
+        let first = self.field(1, 1);
+        let corner = Vec2::new(first.point.x - first.width / 2.0, first.point.y + first.height / 2.0);
+
+        Vec2::new((point.x - corner.x) / first.width, (corner.y - point.y) / first.height)
+    }
+
+    /// Whether the field at a rounded down `grid_space` position is solid
+    fn is_solid_at(&self, cell: Vec2) -> bool {
+        if cell.x < 0.0 || cell.y < 0.0 || cell.x >= self.columns as f32 || cell.y >= self.rows as f32 {
+            return false;
+        }
+
+        self.field(cell.y as usize + 1, cell.x as usize + 1).solid
+    }
+
+    /// Every field a circle touches, with (0, 0) thrown in if it reaches outside the grid
+    pub fn fields_in_radius(&self, point: Vec3, radius: f32) -> Vec<(usize, usize)> {
+        // only the fields under the circle's bounding box can be touched
+        let top_left = self.grid_space(point + Vec3::new(-radius, radius, 0.0)).floor();
+        let bottom_right = self.grid_space(point + Vec3::new(radius, -radius, 0.0)).floor();
+        let rows = (top_left.y as isize).max(0)..=(bottom_right.y as isize).min(self.rows as isize - 1);
+        let columns = (top_left.x as isize).max(0)..=(bottom_right.x as isize).min(self.columns as isize - 1);
+
+        let mut touched = vec![];
+        for row in rows.map(|row| row as usize + 1) {
+            for column in columns.clone().map(|column| column as usize + 1) {
+                let field = self.field(row, column);
+                let half_size = Vec2::new(field.width, field.height) / 2.0;
+                let offset = point.truncate() - field.point.truncate();
+                let closest = offset.clamp(-half_size, half_size);
+
+                if offset.distance(closest) <= radius {
+                    touched.push((row, column));
+                }
+            }
+        }
+
+        if !self.contains_circle(point, radius) {
+            touched.push((0, 0));
+        }
+
+        touched
+    }
+
+    fn contains_circle(&self, point: Vec3, radius: f32) -> bool {
+        let first = self.field(1, 1);
+        let last = self.field(self.rows, self.columns);
+        let min = Vec2::new(first.point.x - first.width / 2.0, last.point.y - last.height / 2.0);
+        let max = Vec2::new(last.point.x + last.width / 2.0, first.point.y + first.height / 2.0);
+
+        point.x - radius >= min.x && point.x + radius <= max.x
+            && point.y - radius >= min.y && point.y + radius <= max.y
+    }
+
+    /// Whether a straight line from `from` to `to` gets there without going through a solid field.
+    /// Walks the fields the line crosses one by one, in order (a DDA traversal).
+    pub fn line_of_sight(&self, from: Vec3, to: Vec3) -> bool {
+        let start = self.grid_space(from);
+        let end = self.grid_space(to);
+        let direction = end - start;
+
+        let mut cell = start.floor();
+        let crossings = (end.floor() - cell).abs();
+        let step = Vec2::new(direction.x.signum(), direction.y.signum());
+        // how much of the line it takes to cross a whole field, and to get into the next column
+        // or row. a line that never changes column or row never gets to the next one
+        let across = (1.0 / direction).abs();
+        let first_crossing = |cell: f32, start: f32, step: f32, across: f32| match across.is_finite() {
+            true => (cell + step.max(0.0) - start).abs() * across,
+            false => f32::INFINITY,
+        };
+        let mut next = Vec2::new(
+            first_crossing(cell.x, start.x, step.x, across.x),
+            first_crossing(cell.y, start.y, step.y, across.y),
+        );
+
+        for _ in 0..=(crossings.x + crossings.y) as usize {
+            if self.is_solid_at(cell) {
+                return false;
+            }
+
+            if next.x < next.y {
+                cell.x += step.x;
+                next.x += across.x;
+            } else {
+                cell.y += step.y;
+                next.y += across.y;
+            }
+        }
+
+        true
+    }
+
     /// Returns a field index for a point
     /// Always >= 1, if 0; not in grid
     /// *or my algorithm is shit, that's possible as well*
@@ -81,7 +229,7 @@ impl Grid {
                 let y = i as f32 * DEFAULT_FIELD_WIDTH + (DEFAULT_FIELD_WIDTH / 2.0) - x_correction;
                 let x = j as f32 * DEFAULT_FIELD_HEIGHT + (DEFAULT_FIELD_HEIGHT / 2.0) - y_correction;
 
-                fields.push(Field { point: Vec3::new(x, y, 0.0), width: DEFAULT_FIELD_WIDTH, height: DEFAULT_FIELD_HEIGHT });
+                fields.push(Field { point: Vec3::new(x, y, 0.0), width: DEFAULT_FIELD_WIDTH, height: DEFAULT_FIELD_HEIGHT, solid: false, spawn: false });
             }
         }
 
@@ -90,7 +238,12 @@ impl Grid {
 
     pub fn field_debug(&self, gizmos: &mut Gizmos) {
         for field in self.fields.iter() {
-            gizmos.rect_2d(field.point.truncate(), 0.0, Vec2::new(field.width, field.height), Color::GREEN);
+            let color = match field.solid {
+                true => Color::RED,
+                false => Color::GREEN,
+            };
+
+            gizmos.rect_2d(field.point.truncate(), 0.0, Vec2::new(field.width, field.height), color);
         }
     }
 }
@@ -100,6 +253,9 @@ struct Field {
     point: Vec3,
     width: f32,
     height: f32,
+    solid: bool,
+    /// Enemies can spawn here
+    spawn: bool,
 }
 
 impl Field {
@@ -108,11 +264,116 @@ impl Field {
     }
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct WaypointDefinition {
+    pub point: (f32, f32),
+    #[serde(default)]
+    pub wait: f32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PatrolRouteDefinition {
+    #[serde(default)]
+    pub mode: PatrolMode,
+    pub waypoints: Vec<WaypointDefinition>,
+}
+
+impl PatrolRouteDefinition {
+    pub fn build(&self) -> PatrolRoute {
+        let waypoints = self.waypoints.iter()
+            .map(|waypoint| Waypoint {
+                point: Vec3::new(waypoint.point.0, waypoint.point.1, 0.0),
+                wait: waypoint.wait,
+            })
+            .collect();
+
+        PatrolRoute::new(waypoints, self.mode)
+    }
+}
+
+/// A map as authored in `assets/maps/*.map.ron`
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct MapDefinition {
+    pub rows: usize,
+    pub columns: usize,
+    /// (row, column) of every field that can't be walked through, counting from 1 like the grid
+    #[serde(default)]
+    pub solid: Vec<(usize, usize)>,
+    /// (row, column) of every field enemies can spawn on
+    #[serde(default)]
+    pub spawn: Vec<(usize, usize)>,
+    #[serde(default)]
+    pub patrol_routes: Vec<PatrolRouteDefinition>,
+}
+
+impl MapDefinition {
+    pub fn build_grid(&self) -> Grid {
+        let mut grid = Grid::new(self.rows, self.columns);
+
+        for (row, column) in self.solid.iter() {
+            grid.set_solid(*row, *column);
+        }
+
+        for (row, column) in self.spawn.iter() {
+            grid.set_spawn(*row, *column);
+        }
+
+        grid
+    }
+}
+
+/// The patrol routes of the current map, living on the grid entity
+#[derive(Component, Debug)]
+pub struct PatrolRoutes(pub Vec<PatrolRoute>);
+
 #[derive(Component)]
-pub struct FieldCoordinates(pub Vec<(usize, usize)>);
+pub struct FieldCoordinates(#[allow(dead_code)] pub Vec<(usize, usize)>);
+
+/// Which actors are in which field, so looking for actors around a point only has to look at the
+/// fields around it. Rebuilt along with the `FieldCoordinates` every update.
+#[derive(Resource, Default, Debug)]
+pub struct FieldOccupants(HashMap<(usize, usize), Vec<Entity>>);
+
+impl FieldOccupants {
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    pub fn insert(&mut self, coordinates: &[(usize, usize)], entity: Entity) {
+        for coordinate in coordinates {
+            self.0.entry(*coordinate).or_default().push(entity);
+        }
+    }
+
+    /// Everything in the given fields, once each
+    pub fn in_fields(&self, coordinates: &[(usize, usize)]) -> Vec<Entity> {
+        let mut entities: Vec<Entity> = coordinates.iter()
+            .filter_map(|coordinate| self.0.get(coordinate))
+            .flatten()
+            .copied()
+            .collect();
+        entities.sort();
+        entities.dedup();
+
+        entities
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arena_map_parses() {
+        let map: MapDefinition = ron::from_str(include_str!("../../assets/maps/arena.map.ron")).unwrap();
+
+        assert_eq!(map.rows * map.columns, Grid::new(map.rows, map.columns).fields.len());
+        assert!(map.patrol_routes.iter().all(|route| !route.build().is_finished()));
+        assert_eq!(map.build_grid().spawn_points().len(), map.spawn.len());
 
-#[test]
-fn name() {
-    fn it_works() {
+        // corner to corner, around the pillars
+        let navmesh = ranger_ai::NavMesh::build(&map.build_grid().walkable_grid(), 25.0);
+        let path = navmesh.find_path(Vec3::new(-300.0, 300.0, 0.0), Vec3::new(300.0, -300.0, 0.0)).unwrap();
+        assert_eq!(path.last(), Some(&Vec3::new(300.0, -300.0, 0.0)));
     }
 }
@@ -1,13 +1,65 @@
 use bevy::prelude::*;
 use ranger_physics::*;
 
-mod physics;
-mod map;
+pub mod physics;
+pub mod map;
+pub mod influence;
+pub mod navigation;
 
+#[derive(Resource)]
+pub struct CurrentMap(pub Handle<map::MapDefinition>);
+
+fn load_map(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    commands.insert_resource(CurrentMap(asset_server.load("maps/arena.map.ron")));
+}
+
+/// (Re)builds the grid once the map is loaded, and whenever the map file changes.
+/// Solid fields get a sprite, which lives as a child of the grid.
 fn init_grid(
+    mut map_events: EventReader<AssetEvent<map::MapDefinition>>,
+    grid_query: Query<Entity, With<map::Grid>>,
     mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    res_current_map: Res<CurrentMap>,
+    res_maps: Res<Assets<map::MapDefinition>>,
 ) {
-    commands.spawn(map::Grid::new(9, 9));
+    for event in map_events.read() {
+        if !event.is_loaded_with_dependencies(&res_current_map.0) && !event.is_modified(&res_current_map.0) {
+            continue;
+        }
+
+        let Some(definition) = res_maps.get(&res_current_map.0) else {
+            continue;
+        };
+
+        for entity in grid_query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+
+        let grid = definition.build_grid();
+        let solid_fields: Vec<(Vec3, Vec2)> = grid.solid_fields().collect();
+
+        commands.spawn((
+            grid,
+            map::PatrolRoutes(definition.patrol_routes.iter().map(map::PatrolRouteDefinition::build).collect()),
+            SpatialBundle::default(),
+        )).with_children(|parent| {
+            for (point, size) in solid_fields {
+                parent.spawn(SpriteBundle {
+                    sprite: Sprite {
+                        custom_size: Some(size),
+                        ..default()
+                    },
+                    texture: asset_server.load("sprites/placeholder.png"),
+                    transform: Transform::from_translation(point.truncate().extend(-1.0)),
+                    ..default()
+                });
+            }
+        });
+    }
 }
 
 fn debug_grid(
@@ -19,23 +71,30 @@ fn debug_grid(
     }
 }
 
-// TODO: actually add the component to the entity
+/// Works out which fields every actor is in. Bullets are left out, there's too many of them and
+/// nothing needs to look them up by field.
+#[allow(clippy::type_complexity)]
 pub fn set_field_coords(
-    actor_query: Query<(Entity, &Transform, Option<&AABB>), With<Path>>,
+    actor_query: Query<(Entity, &Transform, Option<&AABB>), (With<Path>, With<crate::actor::Health>)>,
     grid_query: Query<&map::Grid>,
     mut commands: Commands,
+    mut res_occupants: ResMut<map::FieldOccupants>,
 ) {
     if let Err(_) = grid_query.get_single() {
         return;
     }
 
     let grid = grid_query.single();
+    res_occupants.clear();
 
     for (entity, transform, aabb) in actor_query.iter() {
-        match aabb {
-            Some(aabb) => commands.entity(entity).insert(self::map::FieldCoordinates(grid.associate_aabb(aabb))),
-            None => commands.entity(entity).insert(self::map::FieldCoordinates(grid.associate_point(&transform.translation))),
+        let coordinates = match aabb {
+            Some(aabb) => grid.associate_aabb(aabb),
+            None => grid.associate_point(&transform.translation),
         };
+
+        res_occupants.insert(&coordinates, entity);
+        commands.entity(entity).insert(self::map::FieldCoordinates(coordinates));
     }
 }
 
@@ -50,8 +109,15 @@ impl Plugin for WorldPlugin {
                 ));
         }
         app
-            .add_plugins(physics::PhysicsPlugin)
-            .add_systems(Startup, init_grid)
-            .add_systems(Update, set_field_coords);
+            .init_asset::<map::MapDefinition>()
+            .init_resource::<map::FieldOccupants>()
+            .register_asset_loader(ranger_ai::RonLoader::<map::MapDefinition>::new(&["map.ron"]))
+            .add_plugins((
+                physics::PhysicsPlugin,
+                navigation::NavigationPlugin,
+                influence::InfluencePlugin,
+            ))
+            .add_systems(Startup, load_map)
+            .add_systems(Update, (init_grid, set_field_coords));
     }
 }
@@ -0,0 +1,77 @@
+use bevy::prelude::*;
+use ranger_physics::{AABB, Path};
+use ranger_ai::{avoidance::Agent, NavMesh, NavPath, WalkableGrid};
+
+/// One navmesh per agent size, built the first time an agent of that size asks for one.
+/// Everything gets thrown out whenever the grid changes.
+#[derive(Resource, Default)]
+pub struct NavMeshes {
+    grid: Option<WalkableGrid>,
+    meshes: Vec<NavMesh>,
+}
+
+impl NavMeshes {
+    pub fn get(&mut self, agent_radius: f32) -> Option<&NavMesh> {
+        let grid = self.grid.as_ref()?;
+
+        let index = match self.meshes.iter().position(|mesh| mesh.agent_radius == agent_radius) {
+            Some(index) => index,
+            None => {
+                self.meshes.push(NavMesh::build(grid, agent_radius));
+                self.meshes.len() - 1
+            },
+        };
+
+        Some(&self.meshes[index])
+    }
+}
+
+fn rebuild_nav_meshes(
+    grid_query: Query<&super::map::Grid, Changed<super::map::Grid>>,
+    mut nav_path_query: Query<&mut NavPath>,
+    mut res_nav_meshes: ResMut<NavMeshes>,
+) {
+    let Ok(grid) = grid_query.get_single() else {
+        return;
+    };
+
+    res_nav_meshes.grid = Some(grid.walkable_grid());
+    res_nav_meshes.meshes.clear();
+
+    for mut nav_path in nav_path_query.iter_mut() {
+        nav_path.clear();
+    }
+}
+
+/// Steers along the navmesh towards `destination`, planning a new path whenever the destination
+/// moved too far from what the current one was planned for.
+/// Without a grid to plan on, it just heads straight for it.
+pub fn steer_towards(
+    nav_meshes: &mut NavMeshes,
+    aabb: &AABB,
+    nav_path: &mut NavPath,
+    path: &mut Path,
+    position: Vec3,
+    destination: Vec3,
+) {
+    if nav_path.needs_replan(destination) {
+        let waypoints = nav_meshes.get(Agent::radius(aabb))
+            .and_then(|nav_mesh| nav_mesh.find_path(position, destination))
+            .unwrap_or_else(|| vec![destination]);
+
+        nav_path.set(destination, waypoints);
+    }
+
+    let waypoint = nav_path.next(position).unwrap_or(destination);
+    path.steering(&position, &waypoint);
+}
+
+pub struct NavigationPlugin;
+
+impl Plugin for NavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<NavMeshes>()
+            .add_systems(Update, rebuild_nav_meshes);
+    }
+}
@@ -1,37 +1,32 @@
 use bevy::prelude::*;
 use ranger_physics::{AABB, Path};
 
-
-#[derive(Component)]
-struct Correction(Option<Vec3>);
-
 pub fn debug(
-    bounding_box_query: Query<(&AABB, Option<&crate::actor::bullet::Hit>)>,
+    bounding_box_query: Query<&AABB>,
     mut gizmos: Gizmos,
 ) {
-    for (bounding_box, hit) in bounding_box_query.iter() {
-        let color = match hit {
-            Some(_) => Color::RED,
-            None => Color::GREEN,
-        };
-
-        bounding_box.outline(&mut gizmos, color);
+    for bounding_box in bounding_box_query.iter() {
+        bounding_box.outline(&mut gizmos, Color::GREEN);
     }
 }
 
+/// Two actors touching, or about to this update. Sent once per pair, in no particular order.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct CollisionEvent {
+    pub first: Entity,
+    pub second: Entity,
+}
+
 pub fn detect_actor_collisions(
-    actor_query: Query<(&AABB, &Path)>,
+    actor_query: Query<(Entity, &AABB, &Path)>,
+    mut collision_events: EventWriter<CollisionEvent>,
+    res_time: Res<Time>,
 ) {
-    let actors: Vec<(&AABB, &Path)> = actor_query.iter().collect();
-    for i in 0..actors.len() {
-        let (first_aabb, first_path) = actors[i];
-
-        for j in i+1..actors.len() {
-            let (second_aabb, second_path) = actors[j];
-
-            //println!("before they go in: {}, {}", first_path.movement, second_path.movement);
-            if let true = AABB::is_colliding(first_aabb, first_path, second_aabb, second_path) {
-                println!("yippie");
+    let actors: Vec<(Entity, &AABB, &Path)> = actor_query.iter().collect();
+    for (i, (first, first_aabb, first_path)) in actors.iter().enumerate() {
+        for (second, second_aabb, second_path) in actors.iter().skip(i+1) {
+            if AABB::is_colliding(first_aabb, first_path, second_aabb, second_path, res_time.delta_seconds()) {
+                collision_events.send(CollisionEvent { first: *first, second: *second });
             }
         }
     }
@@ -48,6 +43,8 @@ impl Plugin for PhysicsPlugin {
                     debug.after(crate::actor::move_actors),
                 ));
         }
-        app.add_systems(Update, detect_actor_collisions.before(crate::actor::move_actors));
+        app
+            .add_event::<CollisionEvent>()
+            .add_systems(Update, detect_actor_collisions.before(crate::actor::move_actors));
     }
 }